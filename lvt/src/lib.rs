@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
 use anchor_spl::token::{self, Mint, Token, TokenAccount};
 
 declare_id!("7npskT7QVWC6kddvwxfSdVHUZxihPYQmq1qYu3HnNZba");
@@ -15,6 +16,33 @@ pub mod liquidity_velocity_token {
         state.fee_rate = 1000; // Initial fee rate (example units)
         state.last_fee_update = Clock::get()?.unix_timestamp;
         state.treasury = ctx.accounts.treasury.key();
+        state.admin = ctx.accounts.admin.key();
+        state.compliance_authority = ctx.accounts.admin.key();
+        state.trade_venue = ctx.accounts.admin.key();
+        state.lvt_mint = ctx.accounts.lvt_mint.key();
+        state.treasury_authority_bump = ctx.bumps.treasury_authority;
+        state.bump = ctx.bumps.state;
+        // README split: 60% traders & LPs, 20% staking, 10% development, 10% ecosystem.
+        state.treasury_split_bps = [6000, 2000, 1000, 1000];
+        // Claim and vesting parameters.
+        state.claim_cooldown = 3600; // 1 hour between claims
+        state.vesting_cliff = 7 * 86400; // 7 day cliff
+        state.vesting_duration = 30 * 86400; // 30 day linear release
+        state.vesting_accel_bps = 5000; // staking the vesting balance halves the remaining schedule
+        // Stake withdrawal parameters.
+        state.unbonding_period = 7 * 86400; // 7 days, longer than a reward epoch
+        state.early_exit_penalty_bps = 1000; // 10% of the position for breaking a lockup
+        // Reward epochs and leaderboard seasons are advanced by keeper cranks.
+        state.reward_epoch = 0;
+        state.epoch_started_at = state.last_fee_update;
+        state.leaderboard_season = 0;
+        // Governance: 3 day votes, quorum in staked LVT base units.
+        state.proposal_count = 0;
+        state.voting_period = 3 * 86400;
+        state.quorum_votes = 1_000_000;
+        state.timelock_delay = 2 * 86400; // 2 days of notice before a passed proposal executes
+        state.guardian = ctx.accounts.admin.key();
+        state.paused = false;
         // Initialize dynamic reward tracking
         state.reward_sum = 0;
         state.reward_count = 0;
@@ -24,24 +52,59 @@ pub mod liquidity_velocity_token {
 
     /// Record a trade with detailed parameters and log a TradeRecord.
     /// Also update the user’s cumulative stats and dynamic reward (via fixed multiplier adjustment here).
-    pub fn record_trade(
-        ctx: Context<RecordTrade>,
-        trade_amount: u64,
-        trade_timestamp: i64,
-        trade_pair: String,
-        execution_delay: i64,
-        slippage: u64,
-        liquidity_provided: u64,
-        // Parameter for wash trading check – counterparty address.
-        counterparty: Pubkey,
-    ) -> Result<()> {
+    /// Only the trade venue can report fills: they pay out real LVT and rank the leaderboard.
+    pub fn record_trade(ctx: Context<RecordTrade>, params: TradeParams) -> Result<()> {
+        let TradeParams {
+            trade_amount,
+            trade_timestamp,
+            trade_pair,
+            execution_delay,
+            slippage,
+            liquidity_provided,
+            counterparty,
+            side,
+            liquidity,
+            price,
+            reference_price,
+        } = params;
         let state = &mut ctx.accounts.state;
+        require!(!state.paused, CustomError::ProtocolPaused);
         let user_state = &mut ctx.accounts.user_state;
 
         // Anti-wash trading check:
         if user_state.owner == counterparty {
             return Err(CustomError::WashTradingAttempt.into());
         }
+        // Trades between linked wallets, repeated against one counterparty or reversing an
+        // earlier trade are still recorded, but earn nothing and count towards no stats.
+        let current_time = Clock::get()?.unix_timestamp;
+        let mut wash_flags = read_wallet_link_flags(&ctx.accounts.wallet_link, &user_state.owner, &counterparty, ctx.program_id)?;
+        wash_flags |= user_state.observe_counterparty(counterparty, pair_key(&trade_pair), side, current_time);
+        let flagged = wash_flags != 0;
+        if flagged {
+            user_state.flagged_trades = user_state.flagged_trades.checked_add(1).unwrap();
+            user_state.penalize(WASH_PENALTY_BPS, current_time);
+            emit!(WashTradeFlagged {
+                user: user_state.owner,
+                counterparty,
+                flags: wash_flags,
+            });
+        }
+
+        // Institutional accounts count every unflagged trade towards their volume commitment.
+        if user_state.is_institutional && !flagged {
+            let profile = ctx
+                .accounts
+                .institutional_profile
+                .as_mut()
+                .ok_or(CustomError::InstitutionalProfileRequired)?;
+            if profile.is_active(Clock::get()?.unix_timestamp) {
+                profile.volume_to_date = profile.volume_to_date.checked_add(trade_amount).unwrap();
+            } else {
+                // Negotiated terms have lapsed; fall back to the standard schedule.
+                user_state.is_institutional = false;
+            }
+        }
 
         // Update global statistics.
         state.total_trades = state.total_trades.checked_add(1).unwrap();
@@ -51,6 +114,39 @@ pub mod liquidity_velocity_token {
         user_state.trade_count = user_state.trade_count.checked_add(1).unwrap();
         user_state.cumulative_volume = user_state.cumulative_volume.checked_add(trade_amount).unwrap();
 
+        // Pay the strategy boost earned in a finished epoch, then count this fill in the current one.
+        let settled_epoch = user_state.strategy_epoch;
+        let reputation = user_state.reputation_bps(current_time);
+        let (strategy_flags, strategy_boost) = user_state.settle_strategy(state.reward_epoch);
+        let strategy_boost = bps_of(strategy_boost, reputation);
+        if strategy_boost > 0 {
+            user_state.accrued_rewards = user_state.accrued_rewards.checked_add(strategy_boost).unwrap();
+            emit!(StrategyBoostApplied {
+                user: user_state.owner,
+                epoch: settled_epoch,
+                flags: strategy_flags,
+                boost: strategy_boost,
+            });
+        }
+        if !flagged {
+            user_state.record_fill(trade_amount, side, liquidity, price, reference_price);
+
+            // Update the rolling 24h leaderboard stats, and the global ranking if the trader's
+            // reputation makes them eligible.
+            let leaderboard = &mut ctx.accounts.leaderboard;
+            if leaderboard.season != state.leaderboard_season {
+                leaderboard.reset(state.leaderboard_season);
+            }
+            leaderboard.record(trade_amount, current_time);
+            if reputation >= LEADERBOARD_MIN_REPUTATION_BPS {
+                let mut top_n = ctx.accounts.leaderboard_top_n.load_mut()?;
+                if top_n.season != state.leaderboard_season {
+                    top_n.reset(state.leaderboard_season);
+                }
+                top_n.update(leaderboard, current_time);
+            }
+        }
+
         // Create a new TradeRecord for detailed logging.
         let trade_record = &mut ctx.accounts.trade_record;
         trade_record.user = user_state.owner;
@@ -60,6 +156,16 @@ pub mod liquidity_velocity_token {
         trade_record.execution_delay = execution_delay;
         trade_record.slippage = slippage;
         trade_record.liquidity_provided = liquidity_provided;
+        trade_record.epoch = state.reward_epoch;
+        trade_record.side = side;
+        trade_record.liquidity = liquidity;
+        trade_record.price = price;
+        trade_record.reference_price = reference_price;
+        trade_record.flagged = flagged;
+        trade_record.wash_flags = wash_flags;
+        if flagged {
+            return Ok(());
+        }
 
         // Advanced reward calculation:
         let base_reward = trade_amount;
@@ -80,6 +186,12 @@ pub mod liquidity_velocity_token {
             reward = reward / 2;
         }
 
+        // Apply the staking tier's reward boost and the veLVT boost for remaining lock time.
+        reward = bps_of(reward, user_state.tier_boost());
+        reward = bps_of(reward, ve_boost_bps(user_state, current_time));
+        // Scale by reputation: from nothing at the maximum penalty to the full reward.
+        reward = bps_of(reward, reputation);
+
         // Update accrued rewards.
         user_state.accrued_rewards = user_state.accrued_rewards.checked_add(reward).unwrap();
 
@@ -93,8 +205,37 @@ pub mod liquidity_velocity_token {
             state.reward_count = 0;
         }
         // Also update the user’s reward multiplier (could be further adjusted by market conditions off-chain).
-        user_state.reward_multiplier = state.global_reward_multiplier;
+        user_state.reward_multiplier = bps_of(state.global_reward_multiplier, reputation);
+
+        Ok(())
+    }
+
+    /// Declare the caller's wallet linked to another one. Declarations cannot be withdrawn.
+    /// wallet_a and wallet_b are the two wallets in ascending order.
+    pub fn declare_wallet_link(ctx: Context<DeclareWalletLink>, wallet_a: Pubkey, wallet_b: Pubkey) -> Result<()> {
+        require!(wallet_a < wallet_b, CustomError::InvalidWalletLink);
+        let wallet_link = &mut ctx.accounts.wallet_link;
+        wallet_link.wallet_a = wallet_a;
+        wallet_link.wallet_b = wallet_b;
+        wallet_link.declared = true;
+        wallet_link.bump = ctx.bumps.wallet_link;
+        Ok(())
+    }
 
+    /// Admin or compliance: flag two wallets (in ascending order) as linked.
+    pub fn flag_wallet_link(ctx: Context<FlagWalletLink>, wallet_a: Pubkey, wallet_b: Pubkey) -> Result<()> {
+        require!(wallet_a < wallet_b, CustomError::InvalidWalletLink);
+        let wallet_link = &mut ctx.accounts.wallet_link;
+        wallet_link.wallet_a = wallet_a;
+        wallet_link.wallet_b = wallet_b;
+        wallet_link.flagged = true;
+        wallet_link.bump = ctx.bumps.wallet_link;
+        Ok(())
+    }
+
+    /// Admin or compliance: flag or clear an existing link. A declared link stays in force.
+    pub fn set_wallet_link_flag(ctx: Context<SetWalletLinkFlag>, flagged: bool) -> Result<()> {
+        ctx.accounts.wallet_link.flagged = flagged;
         Ok(())
     }
 
@@ -110,40 +251,282 @@ pub mod liquidity_velocity_token {
         Ok(())
     }
 
+    /// Create the program-owned vault that holds staked LVT.
+    pub fn init_stake_vault(_ctx: Context<InitStakeVault>) -> Result<()> {
+        Ok(())
+    }
+
     /// Stake tokens with an optional lockup period for enhanced fee discounts and multi-tier rewards.
+    /// Every deposit opens its own StakePosition with its own lockup, so existing lockups can never
+    /// be shortened. Locked positions add to the user's veLVT aggregates.
     pub fn stake_with_lockup(
         ctx: Context<StakeTokens>,
         amount: u64,
         lockup_duration: i64, // in seconds (e.g., 1 month, 3 months, 6 months)
     ) -> Result<()> {
+        require!(!ctx.accounts.state.paused, CustomError::ProtocolPaused);
+        require!(amount > 0, CustomError::InvalidStakeAmount);
+        require!(
+            (0..=VE_MAX_LOCK).contains(&lockup_duration),
+            CustomError::InvalidLockupDuration
+        );
+        let current_time = Clock::get()?.unix_timestamp;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.stake_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
         let user_state = &mut ctx.accounts.user_state;
+        let position = &mut ctx.accounts.stake_position;
+        position.owner = user_state.owner;
+        position.index = user_state.position_count;
+        position.amount = amount;
+        position.lockup_start = current_time;
+        position.lockup_end = current_time.checked_add(lockup_duration).unwrap();
+        position.yield_multiplier_bps = lockup_yield_multiplier_bps(position.lockup_end - current_time);
+        position.bump = ctx.bumps.stake_position;
+
+        // Aggregate the position into the user's totals and staking pool weight.
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.sync(ctx.accounts.staking_vault.amount);
+        let new_weight = user_state
+            .weighted_stake
+            .checked_add(position.weighted_amount())
+            .unwrap();
+        staking_pool.reweight(user_state, new_weight);
+        user_state.position_count = user_state.position_count.checked_add(1).unwrap();
         user_state.staked_amount = user_state.staked_amount.checked_add(amount).unwrap();
+        user_state.add_lock(position);
 
-        // Set lockup period if specified.
-        if lockup_duration > 0 {
-            let current_time = Clock::get()?.unix_timestamp;
-            user_state.lockup_end = current_time.checked_add(lockup_duration).unwrap();
-        }
-
-        // Update fee discount and trading rebate based on multi-tier staking:
-        if user_state.staked_amount >= 50_000 {
-            user_state.fee_discount = 30; // Tier Pro: 30% discount + instant execution priority
-            user_state.trading_rebate = 10; // Example: 10% trading rebate
-        } else if user_state.staked_amount >= 5_000 {
-            user_state.fee_discount = 20; // Advanced: 20% discount
-            user_state.trading_rebate = 5;  // Example: 5% trading rebate
-        } else if user_state.staked_amount >= 500 {
-            user_state.fee_discount = 10; // Basic: 10% discount
-            user_state.trading_rebate = 0;
-        } else {
-            user_state.fee_discount = 0;
-            user_state.trading_rebate = 0;
+        // Update fee discount, trading rebate, priority and boost from the staking tier table.
+        apply_stake_tier(user_state, &ctx.accounts.tier_config);
+        ctx.accounts.stake_history.record(user_state, current_time);
+        sync_delegation(
+            user_state,
+            ctx.accounts.delegation.as_deref_mut(),
+            ctx.accounts.delegate_state.as_deref_mut(),
+            ctx.accounts.delegate_history.as_deref_mut(),
+            current_time,
+        )
+    }
+
+    /// Start unbonding a position whose lockup has ended. The stake stops counting for tiers immediately.
+    pub fn request_unstake(ctx: Context<RequestUnstake>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let position = &mut ctx.accounts.stake_position;
+        require!(position.unbonding_end == 0, CustomError::PositionAlreadyUnbonding);
+        require!(current_time >= position.lockup_end, CustomError::LockupNotExpired);
+
+        let user_state = &mut ctx.accounts.user_state;
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.sync(ctx.accounts.staking_vault.amount);
+        detach_stake_position(user_state, staking_pool, position);
+        apply_stake_tier(user_state, &ctx.accounts.tier_config);
+        ctx.accounts.stake_history.record(user_state, current_time);
+        sync_delegation(
+            user_state,
+            ctx.accounts.delegation.as_deref_mut(),
+            ctx.accounts.delegate_state.as_deref_mut(),
+            ctx.accounts.delegate_history.as_deref_mut(),
+            current_time,
+        )?;
+        position.unbonding_end = current_time
+            .checked_add(ctx.accounts.state.unbonding_period)
+            .unwrap();
+        Ok(())
+    }
+
+    /// Break a position's remaining lockup. The early-exit penalty is paid to the staking bucket
+    /// and the rest of the position still goes through the unbonding period.
+    pub fn emergency_unstake(ctx: Context<EmergencyUnstake>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let current_time = Clock::get()?.unix_timestamp;
+        let position = &mut ctx.accounts.stake_position;
+        require!(position.unbonding_end == 0, CustomError::PositionAlreadyUnbonding);
+
+        // Sync before detaching so the penalty below is only shared with the remaining stakers.
+        let user_state = &mut ctx.accounts.user_state;
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.sync(ctx.accounts.staking_vault.amount);
+        detach_stake_position(user_state, staking_pool, position);
+        apply_stake_tier(user_state, &ctx.accounts.tier_config);
+        ctx.accounts.stake_history.record(user_state, current_time);
+        sync_delegation(
+            user_state,
+            ctx.accounts.delegation.as_deref_mut(),
+            ctx.accounts.delegate_state.as_deref_mut(),
+            ctx.accounts.delegate_history.as_deref_mut(),
+            current_time,
+        )?;
+
+        if current_time < position.lockup_end {
+            let penalty = bps_of(position.amount, state.early_exit_penalty_bps as u64);
+            if penalty > 0 {
+                treasury_transfer(
+                    &ctx.accounts.token_program,
+                    &ctx.accounts.stake_vault,
+                    &ctx.accounts.staking_vault,
+                    &ctx.accounts.treasury_authority,
+                    &state.key(),
+                    state.treasury_authority_bump,
+                    penalty,
+                )?;
+                let staking_bucket = &mut ctx.accounts.staking_bucket;
+                staking_bucket.total_distributed =
+                    staking_bucket.total_distributed.checked_add(penalty).unwrap();
+                position.amount -= penalty;
+            }
+            position.lockup_end = current_time;
+        }
+        position.unbonding_end = current_time.checked_add(state.unbonding_period).unwrap();
+        Ok(())
+    }
+
+    /// Release an unbonded position back to its owner and close it.
+    pub fn complete_unstake(ctx: Context<CompleteUnstake>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let position = &ctx.accounts.stake_position;
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(position.unbonding_end != 0, CustomError::PositionNotUnbonding);
+        require!(current_time >= position.unbonding_end, CustomError::UnbondingNotComplete);
+        treasury_transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.stake_vault,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.treasury_authority,
+            &state.key(),
+            state.treasury_authority_bump,
+            position.amount,
+        )
+    }
+
+    /// Create the staking pool that distributes the staking bucket to stakers.
+    pub fn init_staking_pool(ctx: Context<InitStakingPool>) -> Result<()> {
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.state = ctx.accounts.state.key();
+        staking_pool.total_weight = 0;
+        staking_pool.acc_reward_per_share = 0;
+        staking_pool.reserved = 0;
+        staking_pool.bump = ctx.bumps.staking_pool;
+        Ok(())
+    }
+
+    /// Permissionless: account for new staking bucket inflows in the reward-per-share accumulator.
+    pub fn sync_staking_pool(ctx: Context<SyncStakingPool>) -> Result<()> {
+        ctx.accounts.staking_pool.sync(ctx.accounts.staking_vault.amount);
+        Ok(())
+    }
+
+    /// Pay out the caller's share of staking pool yield. Independent of trade-based accrued_rewards.
+    pub fn harvest_staking_yield(ctx: Context<HarvestStakingYield>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let user_state = &mut ctx.accounts.user_state;
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.sync(ctx.accounts.staking_vault.amount);
+        let weight = user_state.weighted_stake;
+        staking_pool.reweight(user_state, weight);
+
+        let amount = user_state.pending_staking_yield;
+        require!(amount > 0, CustomError::NothingToHarvest);
+        treasury_transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.staking_vault,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.treasury_authority,
+            &state.key(),
+            state.treasury_authority_bump,
+            amount,
+        )?;
+        user_state.pending_staking_yield = 0;
+        staking_pool.reserved = staking_pool.reserved.checked_sub(amount).unwrap();
+        let staking_bucket = &mut ctx.accounts.staking_bucket;
+        staking_bucket.total_spent = staking_bucket.total_spent.checked_add(amount).unwrap();
+        Ok(())
+    }
+
+    /// Create the staking tier table with the README defaults (Basic 500, Advanced 5,000, Pro 50,000 LVT).
+    pub fn init_tier_config(ctx: Context<InitTierConfig>) -> Result<()> {
+        let tier_config = &mut ctx.accounts.tier_config;
+        tier_config.state = ctx.accounts.state.key();
+        tier_config.decimals = ctx.accounts.lvt_mint.decimals;
+        tier_config.bump = ctx.bumps.tier_config;
+        tier_config.set_tiers(&[
+            // Basic: 10% discount
+            TierParams { min_stake_tokens: 500, fee_discount_bps: 1000, rebate_bps: 0, execution_priority: false, boost_multiplier_bps: 10_000 },
+            // Advanced: 20% discount + 5% trading rebate
+            TierParams { min_stake_tokens: 5_000, fee_discount_bps: 2000, rebate_bps: 500, execution_priority: false, boost_multiplier_bps: 10_000 },
+            // Pro: 30% discount + 10% trading rebate + execution priority
+            TierParams { min_stake_tokens: 50_000, fee_discount_bps: 3000, rebate_bps: 1000, execution_priority: true, boost_multiplier_bps: 10_000 },
+        ])
+    }
+
+    /// Permissionless: re-resolve a user's tier benefits, e.g. after the tier table changed.
+    pub fn refresh_tier(ctx: Context<RefreshTier>) -> Result<()> {
+        apply_stake_tier(&mut ctx.accounts.user_state, &ctx.accounts.tier_config);
+        Ok(())
+    }
+
+    /// Create the bookkeeping account and token vault for one treasury bucket.
+    /// bucket: 0 = Traders & LPs, 1 = Staking, 2 = Development, 3 = Ecosystem.
+    pub fn init_treasury_bucket(ctx: Context<InitTreasuryBucket>, bucket: u8) -> Result<()> {
+        require!((bucket as usize) < TREASURY_BUCKET_COUNT, CustomError::InvalidTreasuryBucket);
+        let treasury_bucket = &mut ctx.accounts.treasury_bucket;
+        treasury_bucket.state = ctx.accounts.state.key();
+        treasury_bucket.bucket = bucket;
+        treasury_bucket.vault = ctx.accounts.bucket_vault.key();
+        treasury_bucket.total_distributed = 0;
+        treasury_bucket.total_spent = 0;
+        treasury_bucket.bump = ctx.bumps.treasury_bucket;
+        Ok(())
+    }
+
+    /// Permissionless: split the undistributed treasury balance into the bucket vaults.
+    /// Rounding dust stays in the treasury for the next distribution.
+    pub fn distribute_treasury(ctx: Context<DistributeTreasury>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let undistributed = ctx.accounts.treasury.amount;
+        require!(undistributed > 0, CustomError::NothingToDistribute);
+
+        let state_key = state.key();
+        let split_bps = state.treasury_split_bps;
+        let buckets = [
+            (&mut ctx.accounts.traders_lps_bucket, &ctx.accounts.traders_lps_vault),
+            (&mut ctx.accounts.staking_bucket, &ctx.accounts.staking_vault),
+            (&mut ctx.accounts.development_bucket, &ctx.accounts.development_vault),
+            (&mut ctx.accounts.ecosystem_bucket, &ctx.accounts.ecosystem_vault),
+        ];
+        for (i, (bucket, vault)) in buckets.into_iter().enumerate() {
+            let share = bps_of(undistributed, split_bps[i] as u64);
+            if share == 0 {
+                continue;
+            }
+            treasury_transfer(
+                &ctx.accounts.token_program,
+                &ctx.accounts.treasury,
+                vault,
+                &ctx.accounts.treasury_authority,
+                &state_key,
+                state.treasury_authority_bump,
+                share,
+            )?;
+            bucket.total_distributed = bucket.total_distributed.checked_add(share).unwrap();
         }
         Ok(())
     }
 
     /// Claim accrued rewards. Enforce minimum cumulative trading volume and cooldown period.
-    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+    /// Rewards are paid from the traders & LPs bucket, either directly or into the user's vesting account.
+    pub fn claim_rewards(ctx: Context<ClaimRewards>, vest: bool) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require!(!state.paused, CustomError::ProtocolPaused);
         let user_state = &mut ctx.accounts.user_state;
         let current_time = Clock::get()?.unix_timestamp;
         // Enforce a minimum cumulative volume to prevent wash trading exploitation.
@@ -151,19 +534,93 @@ pub mod liquidity_velocity_token {
             user_state.cumulative_volume >= 100,
             CustomError::InsufficientLiquidityForRewards
         );
-        // Enforce a cooldown period (e.g., 1 hour) between claims.
+        // Enforce a cooldown period between claims.
         require!(
-            current_time - user_state.last_claim_time >= 3600,
+            current_time - user_state.last_claim_time >= state.claim_cooldown,
             CustomError::MinimumHoldingPeriodNotMet
         );
-        // [Token transfer logic from treasury to the user would be added here]
+        let amount = user_state.accrued_rewards;
+        if amount > 0 {
+            let destination = if vest {
+                let vesting = ctx
+                    .accounts
+                    .vesting_account
+                    .as_mut()
+                    .ok_or(CustomError::VestingAccountRequired)?;
+                vesting.add(amount, current_time, state.vesting_cliff, state.vesting_duration)?;
+                ctx.accounts
+                    .vesting_vault
+                    .as_ref()
+                    .ok_or(CustomError::VestingAccountRequired)?
+            } else {
+                &ctx.accounts.user_token_account
+            };
+            treasury_transfer(
+                &ctx.accounts.token_program,
+                &ctx.accounts.reward_vault,
+                destination,
+                &ctx.accounts.treasury_authority,
+                &state.key(),
+                state.treasury_authority_bump,
+                amount,
+            )?;
+            let reward_bucket = &mut ctx.accounts.reward_bucket;
+            reward_bucket.total_spent = reward_bucket.total_spent.checked_add(amount).unwrap();
+        }
         user_state.accrued_rewards = 0;
         user_state.last_claim_time = current_time;
         Ok(())
     }
 
-    /// Dynamically adjust pool fees based on liquidity and market activity.
-    pub fn adjust_fee_dynamically(ctx: Context<UpdatePoolFees>) -> Result<()> {
+    /// Create the user's vesting account and its token vault.
+    pub fn open_vesting_account(ctx: Context<OpenVestingAccount>) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting_account;
+        vesting.owner = ctx.accounts.owner.key();
+        vesting.vault = ctx.accounts.vesting_vault.key();
+        vesting.bump = ctx.bumps.vesting_account;
+        Ok(())
+    }
+
+    /// Withdraw everything released so far from the user's vesting account.
+    pub fn withdraw_vested(ctx: Context<WithdrawVested>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let current_time = Clock::get()?.unix_timestamp;
+        let amount = ctx.accounts.vesting_account.withdraw(current_time);
+        require!(amount > 0, CustomError::NothingVested);
+        treasury_transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vesting_vault,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.treasury_authority,
+            &state.key(),
+            state.treasury_authority_bump,
+            amount,
+        )
+    }
+
+    /// Accelerate the remaining release of every tranche not accelerated yet, once the user has a
+    /// stake position covering their locked balance for the whole (shortened) schedule.
+    /// Can be applied once per tranche.
+    pub fn accelerate_vesting(ctx: Context<AccelerateVesting>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let position = &ctx.accounts.stake_position;
+        let vesting = &mut ctx.accounts.vesting_account;
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(vesting.locked(current_time) > 0, CustomError::NothingVested);
+        require!(position.unbonding_end == 0, CustomError::PositionAlreadyUnbonding);
+
+        let (locked, new_end) = vesting.accelerate(current_time, state.vesting_accel_bps);
+        require!(locked > 0, CustomError::VestingAlreadyAccelerated);
+        require!(
+            position.amount >= locked && position.lockup_end >= new_end,
+            CustomError::InsufficientStakeForAcceleration
+        );
+        Ok(())
+    }
+
+    /// Keeper crank: dynamically adjust pool fees based on liquidity and market activity.
+    pub fn adjust_fee_dynamically(ctx: Context<StateCrank>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
         let state = &mut ctx.accounts.state;
         // Example: if total liquidity is low, increase fee; if high, decrease fee.
         if state.total_liquidity < 1_000_000 {
@@ -171,11 +628,111 @@ pub mod liquidity_velocity_token {
         } else {
             state.fee_rate = state.fee_rate.checked_sub(100).unwrap();
         }
-        state.last_fee_update = Clock::get()?.unix_timestamp;
+        state.last_fee_update = current_time;
+        ctx.accounts.keeper.pay(CrankKind::FeeAdjust, current_time)
+    }
+
+    /// Keeper crank: start the next reward epoch. The epoch length is the crank's keeper interval.
+    pub fn rollover_epoch(ctx: Context<StateCrank>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let state = &mut ctx.accounts.state;
+        state.reward_epoch = state.reward_epoch.checked_add(1).unwrap();
+        state.epoch_started_at = current_time;
+        emit!(EpochRolledOver {
+            epoch: state.reward_epoch,
+            started_at: current_time,
+        });
+        ctx.accounts.keeper.pay(CrankKind::EpochRollover, current_time)
+    }
+
+    /// Open the prize window for a leaderboard season, current or upcoming. Anyone can fund its
+    /// vault by transferring LVT to it until the window is finalized.
+    pub fn open_prize_window(ctx: Context<OpenPrizeWindow>, season: u64) -> Result<()> {
+        require!(season >= ctx.accounts.state.leaderboard_season, CustomError::PrizeWindowClosed);
+        let prize_window = &mut ctx.accounts.prize_window;
+        prize_window.state = ctx.accounts.state.key();
+        prize_window.season = season;
+        prize_window.vault = ctx.accounts.prize_vault.key();
+        prize_window.bump = ctx.bumps.prize_window;
+        Ok(())
+    }
+
+    /// Keeper crank: close the current leaderboard window. The live velocity ranking is
+    /// snapshotted and the vault is split across it by the prize curve; ranks without a trader
+    /// roll their share into the next window when its vault is passed. Leaderboards from the
+    /// closed season are zeroed the next time they are updated.
+    pub fn finalize_leaderboard_window(ctx: Context<FinalizeLeaderboardWindow>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let pot = ctx.accounts.prize_vault.amount;
+        let prize_window = &mut ctx.accounts.prize_window;
+        {
+            let top_n = ctx.accounts.leaderboard_top_n.load()?;
+            if top_n.season == prize_window.season {
+                (prize_window.winners, prize_window.prizes) = top_n.payouts(pot, current_time);
+            }
+        }
+        prize_window.finalized = true;
+        prize_window.finalized_at = current_time;
+        let allocated = prize_window.prizes.iter().sum::<u64>();
+        let mut rolled_over = 0;
+        if let Some(next_prize_window) = &ctx.accounts.next_prize_window {
+            let next_prize_vault = ctx.accounts.next_prize_vault.as_ref().ok_or(CustomError::InvalidPrizeVault)?;
+            require_keys_eq!(next_prize_vault.key(), next_prize_window.vault, CustomError::InvalidPrizeVault);
+            rolled_over = pot.checked_sub(allocated).unwrap();
+            if rolled_over > 0 {
+                treasury_transfer(
+                    &ctx.accounts.keeper.token_program,
+                    &ctx.accounts.prize_vault,
+                    next_prize_vault,
+                    &ctx.accounts.keeper.treasury_authority.to_account_info(),
+                    &ctx.accounts.state.key(),
+                    ctx.accounts.state.treasury_authority_bump,
+                    rolled_over,
+                )?;
+            }
+        }
+        emit!(LeaderboardWindowFinalized {
+            season: prize_window.season,
+            pot,
+            allocated,
+            rolled_over,
+        });
+
+        let state = &mut ctx.accounts.state;
+        state.leaderboard_season = state.leaderboard_season.checked_add(1).unwrap();
+        ctx.accounts.keeper.pay(CrankKind::LeaderboardReset, current_time)
+    }
+
+    /// Pay a finalized window's prize for `rank` to the trader who held it.
+    pub fn claim_leaderboard_prize(ctx: Context<ClaimLeaderboardPrize>, rank: u8) -> Result<()> {
+        let prize_window = &mut ctx.accounts.prize_window;
+        require!(prize_window.finalized, CustomError::PrizeWindowOpen);
+        let rank = rank as usize;
+        require!(rank < LeaderboardTopN::SIZE, CustomError::NoPrize);
+        require_keys_eq!(prize_window.winners[rank], ctx.accounts.winner.key(), CustomError::NoPrize);
+        let prize = prize_window.prizes[rank];
+        require!(prize > 0 && prize_window.claimed & (1 << rank) == 0, CustomError::NoPrize);
+        prize_window.claimed |= 1 << rank;
+        treasury_transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.prize_vault,
+            &ctx.accounts.winner_token_account,
+            &ctx.accounts.treasury_authority.to_account_info(),
+            &ctx.accounts.state.key(),
+            ctx.accounts.state.treasury_authority_bump,
+            prize,
+        )?;
+        emit!(LeaderboardPrizeClaimed {
+            season: prize_window.season,
+            winner: ctx.accounts.winner.key(),
+            rank: rank as u8,
+            amount: prize,
+        });
         Ok(())
     }
 
-    /// Auto-adjust fee rate based on current market volatility.
+    /// Admin: auto-adjust fee rate based on reported market volatility.
+    /// The volatility input is trusted, so unlike the keeper fee crank this is not permissionless.
     pub fn auto_adjust_fee(ctx: Context<AutoAdjustFee>, current_volatility: u64) -> Result<()> {
         let state = &mut ctx.accounts.state;
         // If market volatility is high, increase fees; otherwise, lower fees.
@@ -188,342 +745,4478 @@ pub mod liquidity_velocity_token {
         Ok(())
     }
 
-    /// Batch trading orders with a randomized delay to help prevent MEV exploitation.
-    pub fn batch_trading_orders_with_delay(ctx: Context<BatchTradingOrders>, delay: i64) -> Result<()> {
-        require!(delay > 0, CustomError::InvalidDelay);
-        msg!("Batch orders will be executed after a delay of {} seconds", delay);
+    /// Register a market. A fee_rate of 0 makes the market follow the global dynamic fee rate.
+    pub fn create_market(
+        ctx: Context<CreateMarket>,
+        pair: String,
+        fee_rate: u64,
+        institutional_fee_rate: u64,
+    ) -> Result<()> {
+        require!(
+            !pair.is_empty() && pair.len() <= Market::MAX_PAIR_LEN,
+            CustomError::InvalidMarketPair
+        );
+        require!(
+            fee_rate <= FEE_RATE_DENOMINATOR && institutional_fee_rate <= FEE_RATE_DENOMINATOR,
+            CustomError::InvalidFeeRate
+        );
+        let market = &mut ctx.accounts.market;
+        market.state = ctx.accounts.state.key();
+        market.pair = pair;
+        market.fee_rate = fee_rate;
+        market.institutional_fee_rate = institutional_fee_rate;
+        market.promo_discount_bps = 0;
+        market.promo_start = 0;
+        market.promo_end = 0;
+        market.bump = ctx.bumps.market;
+        market.auction_count = 0;
+        market.commit_window = 30; // seconds
+        market.reveal_window = 30; // seconds
         Ok(())
     }
 
-    /// Governance-based fee structure update using on-chain voting.
-    pub fn update_fee_structure_by_vote(ctx: Context<UpdateFeeByVote>, new_fee_rate: u64) -> Result<()> {
-        let governance = &mut ctx.accounts.governance;
+    /// Run a fee promotion on a market between promo_start and promo_end.
+    pub fn set_market_promotion(
+        ctx: Context<UpdateMarket>,
+        discount_bps: u16,
+        promo_start: i64,
+        promo_end: i64,
+    ) -> Result<()> {
         require!(
-            governance.vote_count >= governance.required_votes,
-            CustomError::InsufficientVotes
+            discount_bps as u64 <= BPS_DENOMINATOR && promo_start <= promo_end,
+            CustomError::InvalidPromotion
         );
-        let state = &mut ctx.accounts.state;
-        require!(new_fee_rate >= 500 && new_fee_rate <= 5000, CustomError::InvalidFeeRate);
-        state.fee_rate = new_fee_rate;
-        state.last_fee_update = Clock::get()?.unix_timestamp;
-        // Reset vote count after successful update.
-        governance.vote_count = 0;
+        let market = &mut ctx.accounts.market;
+        market.promo_discount_bps = discount_bps;
+        market.promo_start = promo_start;
+        market.promo_end = promo_end;
         Ok(())
     }
 
-    /// Update dynamic reward parameters using a rolling window average.
-    /// The function takes the recent trade reward, current market volatility, and order book gap.
-    pub fn update_dynamic_reward(
-        ctx: Context<UpdateDynamicReward>,
-        recent_reward: u64,
-        market_volatility: u64,
-        order_book_gap: u64,
+    /// Read-only fee quote for a trade of `notional` on `market`, combining the market fee rate,
+    /// institutional schedule, staking tier discount, maker rebate and active promotion.
+    /// The quote is returned through `set_return_data` so venues can CPI it before executing a trade.
+    pub fn quote_fee(ctx: Context<QuoteFee>, notional: u64, side: LiquiditySide) -> Result<FeeQuote> {
+        let current_time = Clock::get()?.unix_timestamp;
+        Ok(compute_fee_quote(
+            &ctx.accounts.state,
+            &ctx.accounts.market,
+            &ctx.accounts.user_state,
+            ctx.accounts.institutional_profile.as_deref(),
+            notional,
+            side,
+            current_time,
+        ))
+    }
+
+    /// Admin: hand institutional whitelisting to a compliance authority.
+    pub fn set_compliance_authority(ctx: Context<SetComplianceAuthority>, compliance_authority: Pubkey) -> Result<()> {
+        ctx.accounts.state.compliance_authority = compliance_authority;
+        Ok(())
+    }
+
+    /// Admin/compliance: whitelist a trader as institutional with a negotiated fee schedule.
+    pub fn open_institutional_profile(
+        ctx: Context<OpenInstitutionalProfile>,
+        fee_rate: u64,
+        volume_commitment: u64,
+        expires_at: i64,
     ) -> Result<()> {
-        let state = &mut ctx.accounts.state;
-        // Update rolling average using recent_reward.
-        state.reward_sum = state.reward_sum.checked_add(recent_reward).unwrap();
-        state.reward_count = state.reward_count.checked_add(1).unwrap();
-        // When the window is complete, update the global multiplier.
-        if state.reward_count >= 50 {
-            let average = state.reward_sum / state.reward_count;
-            // Adjust multiplier based on market conditions.
-            // For example, boost multiplier by 10% if volatility is high or order book gap is wide.
-            let volatility_bonus = if market_volatility > 1000 { 110 } else { 100 };
-            let gap_bonus = if order_book_gap > 500 { 105 } else { 100 };
-            state.global_reward_multiplier = (average * volatility_bonus * gap_bonus) / (100 * 100);
-            state.reward_sum = 0;
-            state.reward_count = 0;
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(fee_rate <= FEE_RATE_DENOMINATOR, CustomError::InvalidFeeRate);
+        require!(expires_at > current_time, CustomError::InvalidInstitutionalTerms);
+        let profile = &mut ctx.accounts.institutional_profile;
+        profile.owner = ctx.accounts.user_state.owner;
+        profile.fee_rate = fee_rate;
+        profile.volume_commitment = volume_commitment;
+        profile.volume_to_date = 0;
+        profile.starts_at = current_time;
+        profile.expires_at = expires_at;
+        profile.approved_by = ctx.accounts.authority.key();
+        profile.bump = ctx.bumps.institutional_profile;
+        ctx.accounts.user_state.is_institutional = true;
+        Ok(())
+    }
+
+    /// Admin/compliance: renegotiate an institutional profile. A new term resets the committed volume.
+    pub fn update_institutional_profile(
+        ctx: Context<UpdateInstitutionalProfile>,
+        fee_rate: u64,
+        volume_commitment: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(fee_rate <= FEE_RATE_DENOMINATOR, CustomError::InvalidFeeRate);
+        require!(expires_at > current_time, CustomError::InvalidInstitutionalTerms);
+        let profile = &mut ctx.accounts.institutional_profile;
+        if !profile.is_active(current_time) {
+            profile.volume_to_date = 0;
+            profile.starts_at = current_time;
         }
+        profile.fee_rate = fee_rate;
+        profile.volume_commitment = volume_commitment;
+        profile.expires_at = expires_at;
+        profile.approved_by = ctx.accounts.authority.key();
+        ctx.accounts.user_state.is_institutional = true;
         Ok(())
     }
 
-    /// Update the leaderboard for trade volume and frequency.
-    pub fn update_leaderboard(ctx: Context<UpdateLeaderboard>, trade_volume: u64, trade_count: u64) -> Result<()> {
-        let leaderboard = &mut ctx.accounts.leaderboard;
-        leaderboard.trade_volume = leaderboard.trade_volume.checked_add(trade_volume).unwrap();
-        leaderboard.trade_count = leaderboard.trade_count.checked_add(trade_count).unwrap();
-        leaderboard.last_update = Clock::get()?.unix_timestamp;
+    /// Admin/compliance: remove a trader from the institutional whitelist.
+    pub fn revoke_institutional_profile(ctx: Context<RevokeInstitutionalProfile>) -> Result<()> {
+        ctx.accounts.user_state.is_institutional = false;
         Ok(())
     }
 
-    /// Reward strategy boosts for specific trading behaviors.
-    /// strategy_type: 1 = Market-making, 2 = Arbitrage, 3 = Options hedging.
-    pub fn reward_strategy_boost(ctx: Context<RewardStrategyBoost>, strategy_type: u8) -> Result<()> {
-        let user_state = &mut ctx.accounts.user_state;
-        match strategy_type {
-            1 => {
-                // Market-making boost
-                user_state.accrued_rewards = user_state.accrued_rewards.checked_add(50).unwrap();
-            }
-            2 => {
-                // Arbitrage boost
-                user_state.accrued_rewards = user_state.accrued_rewards.checked_add(100).unwrap();
-            }
-            3 => {
-                // Options hedging boost
-                user_state.accrued_rewards = user_state.accrued_rewards.checked_add(75).unwrap();
-            }
-            _ => {}
-        }
+    /// Create the zero-copy order queue for a market. Orders wait `batch_delay` seconds before cranking.
+    pub fn init_order_queue(ctx: Context<InitOrderQueue>, batch_delay: i64) -> Result<()> {
+        require!(batch_delay > 0, CustomError::InvalidDelay);
+        let mut order_queue = ctx.accounts.order_queue.load_init()?;
+        order_queue.market = ctx.accounts.market.key();
+        order_queue.batch_delay = batch_delay;
+        order_queue.bump = ctx.bumps.order_queue;
         Ok(())
     }
 
-    /// Randomized batch processing to further prevent front-running.
-    pub fn batch_process_trades(ctx: Context<BatchProcessTrades>) -> Result<()> {
-        let state = &mut ctx.accounts.state;
-        // Simulate a random delay (for demonstration, using clock's timestamp modulus).
-        let random_delay = (Clock::get()?.unix_timestamp % 10) as u64;
-        state.last_fee_update = Clock::get()?.unix_timestamp + random_delay as i64;
-        msg!("Trades will be processed with a randomized delay of {} seconds", random_delay);
+    /// Admin: change how long queued orders wait before they can be cranked.
+    pub fn set_batch_delay(ctx: Context<UpdateOrderQueue>, batch_delay: i64) -> Result<()> {
+        require!(batch_delay > 0, CustomError::InvalidDelay);
+        ctx.accounts.order_queue.load_mut()?.batch_delay = batch_delay;
         Ok(())
     }
 
-    /// Allow LVT token holders to borrow against their staked LVT.
-    /// This is a simplified example of DeFi lending integration.
-    pub fn borrow_against_lvt(ctx: Context<BorrowAgainstLVT>, borrow_amount: u64) -> Result<()> {
-        let user_state = &mut ctx.accounts.user_state;
-        // Ensure the user’s staked collateral is sufficient (e.g., at least 150% of the borrow_amount).
+    /// Queue an order for a later batch. The owner's execution priority and stake score are
+    /// captured at enqueue time so the batch ordering can be recomputed from the queue alone.
+    /// Each owner may have at most MAX_ORDERS_PER_OWNER orders waiting.
+    pub fn enqueue_order(ctx: Context<EnqueueOrder>, side: OrderSide, price: u64, size: u64) -> Result<()> {
+        require!(price > 0 && size > 0, CustomError::InvalidOrder);
+        let user_state = &ctx.accounts.user_state;
+        let mut order_queue = ctx.accounts.order_queue.load_mut()?;
         require!(
-            user_state.staked_amount >= borrow_amount * 150 / 100,
-            CustomError::InsufficientCollateral
+            order_queue.queued_by(&user_state.owner) < OrderQueue::MAX_ORDERS_PER_OWNER,
+            CustomError::TooManyQueuedOrders
         );
-        // Initialize a loan account with a fixed interest rate and due time.
-        let loan = &mut ctx.accounts.loan_account;
-        loan.borrower = user_state.owner;
-        loan.collateral = user_state.staked_amount;
-        loan.borrow_amount = borrow_amount;
-        loan.interest_rate = 5; // Example: 5%
-        loan.start_time = Clock::get()?.unix_timestamp;
-        loan.due_time = Clock::get()?.unix_timestamp + 30 * 86400; // Due in 30 days
+        let id = order_queue.next_order_id;
+        let slot = order_queue
+            .orders
+            .iter_mut()
+            .find(|slot| slot.in_use == 0)
+            .ok_or(CustomError::OrderQueueFull)?;
+        *slot = QueueSlot {
+            owner: user_state.owner,
+            id,
+            price,
+            size,
+            enqueued_at: Clock::get()?.unix_timestamp,
+            stake_score: user_state.weighted_stake,
+            side: side as u8,
+            priority: user_state.execution_priority as u8,
+            in_use: 1,
+            _padding: [0; 5],
+        };
+        order_queue.next_order_id = id.checked_add(1).unwrap();
         Ok(())
     }
-}
 
-//
-// HELPER FUNCTIONS
-//
-
-fn compute_reward_multiplier(accrued: u64, trade_count: u64) -> u64 {
-    if trade_count == 0 { 1 } else { accrued / trade_count }
-}
-
-fn fee_discount_for_stake(staked: u64) -> u64 {
-    if staked >= 50_000 {
-        30  // Tier Pro: 30% discount
-    } else if staked >= 5_000 {
-        20  // Advanced: 20% discount
-    } else if staked >= 500 {
-        10  // Basic: 10% discount
-    } else {
-        0
+    /// Cancel one of the caller's queued orders before it is cranked.
+    pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+        let mut order_queue = ctx.accounts.order_queue.load_mut()?;
+        let market = order_queue.market;
+        let slot = order_queue
+            .orders
+            .iter_mut()
+            .find(|slot| slot.in_use == 1 && slot.id == order_id)
+            .ok_or(CustomError::OrderNotFound)?;
+        require!(slot.owner == ctx.accounts.owner.key(), CustomError::Unauthorized);
+        *slot = QueueSlot::default();
+        ctx.accounts.user_state.record_cancel(Clock::get()?.unix_timestamp);
+        emit!(OrderCancelled {
+            market,
+            order_id,
+            owner: ctx.accounts.owner.key(),
+        });
+        Ok(())
     }
-}
 
-//
-// ACCOUNT CONTEXTS AND STRUCTS
-//
+    /// Keeper crank: pop up to MAX_BATCH of the oldest orders that have waited the queue's
+    /// batch delay and sequence them deterministically: execution-priority (Pro tier) orders first,
+    /// then by stake score, then by arrival. Each order is emitted in execution order and the batch
+    /// is folded into a hash chain venues can check their execution against.
+    /// For MEV-sensitive flow use the commit-reveal batch auction.
+    pub fn crank_batch(ctx: Context<CrankBatch>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let mut order_queue = ctx.accounts.order_queue.load_mut()?;
+        let delay = order_queue.batch_delay;
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(init, payer = admin, space = 8 + State::LEN)]
-    pub state: Account<'info, State>,
-    #[account(mut)]
-    pub treasury: Account<'info, TokenAccount>, // Treasury for LVT tokens.
-    #[account(mut)]
-    pub admin: Signer<'info>,
-    #[account(mut)]
-    pub lvt_mint: Account<'info, Mint>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-}
+        let mut ready: Vec<usize> = (0..OrderQueue::MAX_ORDERS)
+            .filter(|&i| {
+                let slot = &order_queue.orders[i];
+                slot.in_use == 1 && slot.enqueued_at + delay <= current_time
+            })
+            .collect();
+        require!(!ready.is_empty(), CustomError::EmptyBatch);
+        ready.sort_by_key(|&i| order_queue.orders[i].id);
+        ready.truncate(OrderQueue::MAX_BATCH);
 
-#[account]
-pub struct State {
-    pub total_trades: u64,
-    pub total_liquidity: u64,
-    pub fee_rate: u64,
-    pub last_fee_update: i64,
-    pub treasury: Pubkey,
-    // For dynamic reward adjustment:
-    pub reward_sum: u64,
-    pub reward_count: u64,
-    pub global_reward_multiplier: u64,
-    // Additional governance or protocol fields can be added here.
-}
+        let mut batch: Vec<QueuedOrder> = ready.iter().map(|&i| order_queue.orders[i].to_order()).collect();
+        for &i in &ready {
+            order_queue.orders[i] = QueueSlot::default();
+        }
+        sequence_orders(&mut batch);
 
-impl State {
-    // Calculation: 3 u64 fields (24 bytes) + last_fee_update (8) + treasury (32) = 64;
-    // plus 3 more u64 fields (24) = 88 bytes.
-    pub const LEN: usize = 88;
-}
+        let market = order_queue.market;
+        let batch_id = order_queue.last_batch_id.checked_add(1).unwrap();
+        let order_ids: Vec<u64> = batch.iter().map(|order| order.id).collect();
+        let batch_hash = batch_sequence_hash(&order_queue.last_batch_hash, batch_id, &order_ids);
+        order_queue.last_batch_id = batch_id;
+        order_queue.last_batch_hash = batch_hash;
 
-#[derive(Accounts)]
-pub struct RecordTrade<'info> {
-    #[account(mut)]
-    pub state: Account<'info, State>,
-    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
-    pub user_state: Account<'info, UserState>,
-    // Log detailed trade data.
-    #[account(init, payer = payer, space = 8 + TradeRecord::LEN)]
-    pub trade_record: Account<'info, TradeRecord>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        for (sequence, order) in batch.iter().enumerate() {
+            emit!(OrderProcessed {
+                market,
+                batch_id,
+                sequence: sequence as u64,
+                order_id: order.id,
+                owner: order.owner,
+                side: order.side,
+                price: order.price,
+                size: order.size,
+            });
+        }
+        emit!(OrderBatchSequenced {
+            market,
+            batch_id,
+            order_ids,
+            batch_hash,
+        });
+        ctx.accounts.keeper.pay(CrankKind::BatchProcess, current_time)
+    }
 
-#[account]
-pub struct TradeRecord {
-    pub user: Pubkey,
-    pub trade_amount: u64,
-    pub trade_timestamp: i64,
-    pub trade_pair: String, // Consider fixed-length or hashed representation in production.
-    pub execution_delay: i64,
-    pub slippage: u64,
-    pub liquidity_provided: u64,
-}
+    /// Admin: set the commit and reveal windows used by new batch auctions on a market.
+    pub fn set_auction_windows(ctx: Context<UpdateMarket>, commit_window: i64, reveal_window: i64) -> Result<()> {
+        require!(commit_window > 0 && reveal_window > 0, CustomError::InvalidDelay);
+        let market = &mut ctx.accounts.market;
+        market.commit_window = commit_window;
+        market.reveal_window = reveal_window;
+        Ok(())
+    }
 
-impl TradeRecord {
-    // For example, trade_pair is limited to 32 bytes.
-    pub const LEN: usize = 32 + 8 + 8 + 32 + 8 + 8 + 8;
-}
+    /// Permissionless: open the next commit-reveal batch auction on a market.
+    pub fn open_batch_auction(ctx: Context<OpenBatchAuction>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let market = &mut ctx.accounts.market;
+        let auction = &mut ctx.accounts.batch_auction;
+        auction.market = market.key();
+        auction.batch_id = market.auction_count;
+        auction.commit_end = current_time.checked_add(market.commit_window).unwrap();
+        auction.reveal_end = auction.commit_end.checked_add(market.reveal_window).unwrap();
+        auction.commitment_count = 0;
+        auction.orders = Vec::new();
+        auction.settled = false;
+        auction.clearing_price = 0;
+        auction.cleared_volume = 0;
+        auction.bump = ctx.bumps.batch_auction;
+        auction.bond_vault = ctx.accounts.bond_vault.key();
+        market.auction_count = market.auction_count.checked_add(1).unwrap();
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct RecordLiquidityDeposit<'info> {
+    /// Commit phase: submit H(auction || owner || side || price || size || salt) for an order.
+    /// Nothing about the order is visible until the commit window has closed. The LVT `bond` is
+    /// escrowed until the commitment is closed; it must cover AUCTION_BOND_BPS of the order's
+    /// notional once revealed. Commitments are capped at the auction's order capacity.
+    pub fn commit_order(ctx: Context<CommitOrder>, commitment: [u8; 32], bond: u64) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let auction = &mut ctx.accounts.batch_auction;
+        require!(current_time < auction.commit_end, CustomError::AuctionPhaseClosed);
+        require!(
+            auction.commitment_count < BatchAuction::MAX_ORDERS as u64,
+            CustomError::OrderQueueFull
+        );
+        require!(bond >= AUCTION_MIN_BOND, CustomError::InsufficientBond);
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.bond_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            bond,
+        )?;
+        let user_state = &ctx.accounts.user_state;
+        let order_commitment = &mut ctx.accounts.order_commitment;
+        order_commitment.auction = auction.key();
+        order_commitment.owner = user_state.owner;
+        order_commitment.commitment = commitment;
+        order_commitment.committed_at = current_time;
+        order_commitment.priority = user_state.execution_priority;
+        order_commitment.stake_score = user_state.weighted_stake;
+        order_commitment.sequence = auction.commitment_count;
+        order_commitment.revealed = false;
+        order_commitment.bump = ctx.bumps.order_commitment;
+        order_commitment.bond = bond;
+        auction.commitment_count = auction.commitment_count.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    /// Reveal phase: open a commitment. Orders that are never revealed are not settled and
+    /// forfeit their bond.
+    pub fn reveal_order(
+        ctx: Context<RevealOrder>,
+        side: OrderSide,
+        price: u64,
+        size: u64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        require!(price > 0 && size > 0, CustomError::InvalidOrder);
+        let current_time = Clock::get()?.unix_timestamp;
+        let auction = &mut ctx.accounts.batch_auction;
+        require!(
+            current_time >= auction.commit_end && current_time < auction.reveal_end,
+            CustomError::AuctionPhaseClosed
+        );
+        let order_commitment = &mut ctx.accounts.order_commitment;
+        require!(!order_commitment.revealed, CustomError::CommitmentAlreadyRevealed);
+        let expected = order_commitment_hash(&auction.key(), &order_commitment.owner, side, price, size, &salt);
+        require!(expected == order_commitment.commitment, CustomError::CommitmentMismatch);
+        require!(
+            order_commitment.bond >= auction_bond_required(price, size),
+            CustomError::InsufficientBond
+        );
+        require!(auction.orders.len() < BatchAuction::MAX_ORDERS, CustomError::OrderQueueFull);
+
+        auction.orders.push(AuctionOrder {
+            order: QueuedOrder {
+                id: order_commitment.sequence,
+                owner: order_commitment.owner,
+                side,
+                price,
+                size,
+                enqueued_at: order_commitment.committed_at,
+                priority: order_commitment.priority,
+                stake_score: order_commitment.stake_score,
+            },
+            filled: 0,
+        });
+        order_commitment.revealed = true;
+        Ok(())
+    }
+
+    /// Permissionless crank: settle every revealed order at a single uniform clearing price.
+    /// The price maximises matched volume; marginal fills go by price, then execution priority,
+    /// stake score and commit order.
+    pub fn settle_batch_auction(ctx: Context<SettleBatchAuction>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let auction = &mut ctx.accounts.batch_auction;
+        require!(current_time >= auction.reveal_end, CustomError::AuctionPhaseClosed);
+        require!(!auction.settled, CustomError::AuctionAlreadySettled);
+
+        let (clearing_price, volume) = uniform_clearing_price(&auction.orders);
+        if volume > 0 {
+            allocate_auction_fills(&mut auction.orders, OrderSide::Bid, clearing_price, volume);
+            allocate_auction_fills(&mut auction.orders, OrderSide::Ask, clearing_price, volume);
+        }
+        auction.clearing_price = clearing_price;
+        auction.cleared_volume = volume;
+        auction.settled = true;
+
+        emit!(BatchAuctionSettled {
+            market: auction.market,
+            batch_id: auction.batch_id,
+            clearing_price,
+            cleared_volume: volume,
+            revealed_orders: auction.orders.len() as u64,
+            commitments: auction.commitment_count,
+        });
+        Ok(())
+    }
+
+    /// Close a commitment once its auction has settled, returning the rent to its owner.
+    /// Anyone can close it. A revealed commitment's bond is refunded; one that was never revealed
+    /// forfeits its bond to the staking bucket and costs the owner reputation.
+    pub fn close_order_commitment(ctx: Context<CloseOrderCommitment>) -> Result<()> {
+        require!(ctx.accounts.batch_auction.settled, CustomError::AuctionNotSettled);
+        let state = &ctx.accounts.state;
+        let bond = ctx.accounts.order_commitment.bond;
+        let revealed = ctx.accounts.order_commitment.revealed;
+        let destination = if revealed {
+            &ctx.accounts.owner_token_account
+        } else {
+            ctx.accounts.user_state.penalize(UNREVEALED_COMMITMENT_PENALTY_BPS, Clock::get()?.unix_timestamp);
+            &ctx.accounts.staking_vault
+        };
+        if bond > 0 {
+            treasury_transfer(
+                &ctx.accounts.token_program,
+                &ctx.accounts.bond_vault,
+                destination,
+                &ctx.accounts.treasury_authority,
+                &state.key(),
+                state.treasury_authority_bump,
+                bond,
+            )?;
+            if !revealed {
+                let staking_bucket = &mut ctx.accounts.staking_bucket;
+                staking_bucket.total_distributed = staking_bucket.total_distributed.checked_add(bond).unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a governance proposal. Voting starts immediately and lasts State.voting_period.
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: ProposalAction) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let user_state = &ctx.accounts.user_state;
+        require!(
+            user_state.ve_power(current_time) > 0,
+            CustomError::NoVotingPower
+        );
+        action.validate()?;
+        let state = &mut ctx.accounts.state;
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.state = state.key();
+        proposal.id = state.proposal_count;
+        proposal.proposer = ctx.accounts.owner.key();
+        proposal.action = action;
+        // Balances as of the end of the previous checkpoint period; stake moved since does not count.
+        proposal.snapshot_period = stake_checkpoint_period(current_time) - 1;
+        proposal.voting_starts = current_time;
+        proposal.voting_ends = current_time + state.voting_period;
+        proposal.for_votes = 0;
+        proposal.against_votes = 0;
+        proposal.executed = false;
+        proposal.eta = 0;
+        proposal.vetoed = false;
+        proposal.bump = ctx.bumps.proposal;
+        state.proposal_count = state.proposal_count.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    /// Permissionless: create the (empty) ballot of `voter` on a proposal. A delegator voting
+    /// directly needs their delegate's ballot to exist so the override can be recorded on it.
+    pub fn open_vote_record(ctx: Context<OpenVoteRecord>, voter: Pubkey) -> Result<()> {
+        let vote_record = &mut ctx.accounts.vote_record;
+        vote_record.proposal = ctx.accounts.proposal.key();
+        vote_record.voter = voter;
+        vote_record.support = false;
+        vote_record.weight = 0;
+        vote_record.bump = ctx.bumps.vote_record;
+        vote_record.voted = false;
+        vote_record.overridden_power = 0;
+        Ok(())
+    }
+
+    /// Vote on an open proposal with the voter's veLVT plus veLVT delegated to them, both taken
+    /// from the voter's stake history as of the proposal's snapshot period.
+    /// Delegators who vote directly take their power back from their snapshot delegate's ballot.
+    /// Lock decay is evaluated at the start of voting so every ballot uses the same clock.
+    pub fn cast_vote(ctx: Context<CastVote>, support: bool) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(
+            current_time >= proposal.voting_starts && current_time < proposal.voting_ends,
+            CustomError::VotingClosed
+        );
+        let as_of = proposal.voting_starts;
+        let checkpoint = ctx.accounts.stake_history.at(proposal.snapshot_period)?;
+        let vote_record = &mut ctx.accounts.vote_record;
+        require!(!vote_record.voted, CustomError::AlreadyVoted);
+
+        if checkpoint.delegate != Pubkey::default() {
+            let delegate_vote_record = ctx
+                .accounts
+                .delegate_vote_record
+                .as_mut()
+                .ok_or(CustomError::MissingDelegation)?;
+            require_keys_eq!(delegate_vote_record.voter, checkpoint.delegate, CustomError::MissingDelegation);
+            proposal.override_delegate(delegate_vote_record, checkpoint.ve_power(as_of));
+        }
+
+        let power = checkpoint
+            .ve_power(as_of)
+            .checked_add(checkpoint.delegated_ve_power(as_of))
+            .unwrap();
+        proposal.count_vote(vote_record, support, power)
+    }
+
+    /// Assign the caller's veLVT voting power to `delegate`.
+    pub fn delegate_votes(ctx: Context<DelegateVotes>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let user_state = &mut ctx.accounts.user_state;
+        let delegate_state = &mut ctx.accounts.delegate_state;
+        require_keys_neq!(delegate_state.owner, user_state.owner, CustomError::InvalidDelegate);
+        let delegation = &mut ctx.accounts.delegation;
+        delegation.delegator = user_state.owner;
+        delegation.delegate = delegate_state.owner;
+        delegation.bump = ctx.bumps.delegation;
+        delegation.track(user_state, current_time);
+        delegate_state.credit_delegation(delegation);
+        user_state.delegate = delegate_state.owner;
+
+        ctx.accounts.stake_history.record(user_state, current_time);
+        ctx.accounts.delegate_history.record(delegate_state, current_time);
+        Ok(())
+    }
+
+    /// Move an existing delegation to a new delegate.
+    pub fn redelegate(ctx: Context<Redelegate>) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        let delegation = &mut ctx.accounts.delegation;
+        let old_delegate_state = &mut ctx.accounts.old_delegate_state;
+        let new_delegate_state = &mut ctx.accounts.new_delegate_state;
+        require!(
+            new_delegate_state.owner != user_state.owner && new_delegate_state.owner != old_delegate_state.owner,
+            CustomError::InvalidDelegate
+        );
+        let current_time = Clock::get()?.unix_timestamp;
+        old_delegate_state.debit_delegation(delegation);
+        delegation.track(user_state, current_time);
+        new_delegate_state.credit_delegation(delegation);
+        delegation.delegate = new_delegate_state.owner;
+        user_state.delegate = new_delegate_state.owner;
+
+        ctx.accounts.stake_history.record(user_state, current_time);
+        ctx.accounts.old_delegate_history.record(old_delegate_state, current_time);
+        ctx.accounts.new_delegate_history.record(new_delegate_state, current_time);
+        Ok(())
+    }
+
+    /// End a delegation; the voting power returns to the delegator.
+    pub fn revoke_delegation(ctx: Context<RevokeDelegation>) -> Result<()> {
+        ctx.accounts.delegate_state.debit_delegation(&ctx.accounts.delegation);
+        ctx.accounts.user_state.delegate = Pubkey::default();
+
+        let current_time = Clock::get()?.unix_timestamp;
+        ctx.accounts.stake_history.record(&ctx.accounts.user_state, current_time);
+        ctx.accounts.delegate_history.record(&ctx.accounts.delegate_state, current_time);
+        Ok(())
+    }
+
+    /// Permissionless: re-sync a delegation with its delegator's stake, e.g. once their lock expired
+    /// so it stops weighing on the delegate's aggregate.
+    pub fn refresh_delegation(ctx: Context<RefreshDelegation>) -> Result<()> {
+        sync_delegation(
+            &ctx.accounts.user_state,
+            Some(&mut ctx.accounts.delegation),
+            Some(&mut ctx.accounts.delegate_state),
+            Some(&mut ctx.accounts.delegate_history),
+            Clock::get()?.unix_timestamp,
+        )
+    }
+
+    /// Create the caller's stake checkpoint history, seeded with their current balances.
+    /// Required before staking, unstaking, delegating or voting.
+    pub fn init_stake_history(ctx: Context<InitStakeHistory>) -> Result<()> {
+        let stake_history = &mut ctx.accounts.stake_history;
+        stake_history.owner = ctx.accounts.owner.key();
+        stake_history.truncated = false;
+        stake_history.checkpoints = Vec::new();
+        stake_history.bump = ctx.bumps.stake_history;
+        stake_history.record(&ctx.accounts.user_state, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    /// Permissionless: move a proposal that passed (quorum and a majority for) into the timelock.
+    /// The emitted ETA gives integrators notice before the action can execute.
+    pub fn queue_proposal(ctx: Context<QueueProposal>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let state = &ctx.accounts.state;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(proposal.eta == 0, CustomError::ProposalAlreadyQueued);
+        require!(current_time >= proposal.voting_ends, CustomError::VotingNotEnded);
+        require!(proposal.passed(state.quorum_votes), CustomError::InsufficientVotes);
+        proposal.eta = current_time + state.timelock_delay;
+        emit!(ProposalQueued {
+            proposal: proposal.key(),
+            id: proposal.id,
+            action: proposal.action.clone(),
+            eta: proposal.eta,
+        });
+        Ok(())
+    }
+
+    /// Guardian: cancel a queued proposal while its timelock is still running.
+    pub fn veto_proposal(ctx: Context<VetoProposal>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let proposal = &mut ctx.accounts.proposal;
+        require!(proposal.eta != 0, CustomError::ProposalNotQueued);
+        require!(!proposal.executed, CustomError::ProposalAlreadyExecuted);
+        require!(current_time < proposal.eta, CustomError::TimelockExpired);
+        proposal.vetoed = true;
+        emit!(ProposalVetoed {
+            proposal: proposal.key(),
+            id: proposal.id,
+            guardian: ctx.accounts.guardian.key(),
+        });
+        Ok(())
+    }
+
+    /// Permissionless: apply a queued proposal's action once its timelock has elapsed.
+    /// Tier edits need the tier config; treasury spends need the bucket, its vault and the recipient.
+    pub fn execute_proposal(ctx: Context<ExecuteProposal>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let state_key = ctx.accounts.state.key();
+        let proposal = &mut ctx.accounts.proposal;
+        require!(!proposal.executed, CustomError::ProposalAlreadyExecuted);
+        require!(!proposal.vetoed, CustomError::ProposalVetoed);
+        require!(proposal.eta != 0, CustomError::ProposalNotQueued);
+        require!(current_time >= proposal.eta, CustomError::TimelockActive);
+        proposal.executed = true;
+
+        let state = &mut ctx.accounts.state;
+        match proposal.action.clone() {
+            ProposalAction::SetFeeRate { fee_rate } => {
+                state.fee_rate = fee_rate;
+                state.last_fee_update = current_time;
+            }
+            ProposalAction::SetTiers { tiers } => {
+                let tier_config = ctx
+                    .accounts
+                    .tier_config
+                    .as_mut()
+                    .ok_or(CustomError::MissingProposalAccount)?;
+                tier_config.set_tiers(&tiers)?;
+            }
+            ProposalAction::TreasurySpend { bucket, recipient, amount } => {
+                let missing = || error!(CustomError::MissingProposalAccount);
+                let treasury_bucket = ctx.accounts.treasury_bucket.as_mut().ok_or_else(missing)?;
+                let bucket_vault = ctx.accounts.bucket_vault.as_ref().ok_or_else(missing)?;
+                let recipient_account = ctx.accounts.recipient.as_ref().ok_or_else(missing)?;
+                let treasury_authority = ctx.accounts.treasury_authority.as_ref().ok_or_else(missing)?;
+                let token_program = ctx.accounts.token_program.as_ref().ok_or_else(missing)?;
+                require!(
+                    treasury_bucket.bucket == bucket
+                        && bucket_vault.key() == treasury_bucket.vault
+                        && recipient_account.key() == recipient,
+                    CustomError::MissingProposalAccount
+                );
+                treasury_transfer(
+                    token_program,
+                    bucket_vault,
+                    recipient_account,
+                    treasury_authority,
+                    &state_key,
+                    state.treasury_authority_bump,
+                    amount,
+                )?;
+                treasury_bucket.total_spent = treasury_bucket.total_spent.checked_add(amount).unwrap();
+            }
+            ProposalAction::SetPaused { paused } => {
+                state.paused = paused;
+            }
+            ProposalAction::SetTreasurySplit { split_bps } => {
+                state.treasury_split_bps = split_bps;
+            }
+            ProposalAction::SetClaimConfig {
+                claim_cooldown,
+                vesting_cliff,
+                vesting_duration,
+                vesting_accel_bps,
+            } => {
+                state.claim_cooldown = claim_cooldown;
+                state.vesting_cliff = vesting_cliff;
+                state.vesting_duration = vesting_duration;
+                state.vesting_accel_bps = vesting_accel_bps;
+            }
+            ProposalAction::SetUnstakeConfig {
+                unbonding_period,
+                early_exit_penalty_bps,
+            } => {
+                state.unbonding_period = unbonding_period;
+                state.early_exit_penalty_bps = early_exit_penalty_bps;
+            }
+            ProposalAction::SetGovernanceConfig {
+                voting_period,
+                quorum_votes,
+                timelock_delay,
+            } => {
+                state.voting_period = voting_period;
+                state.quorum_votes = quorum_votes;
+                state.timelock_delay = timelock_delay;
+            }
+            ProposalAction::SetGuardian { guardian } => {
+                state.guardian = guardian;
+            }
+            ProposalAction::SetMmBudget { market, epoch_budget } => {
+                let mm_program = ctx.accounts.mm_program.as_mut().ok_or(CustomError::MissingProposalAccount)?;
+                require_keys_eq!(mm_program.market, market, CustomError::MissingProposalAccount);
+                // Takes effect for the epoch in progress.
+                mm_program.epoch_budget = epoch_budget;
+            }
+        }
+        emit!(ProposalExecuted {
+            proposal: proposal.key(),
+            id: proposal.id,
+        });
+        Ok(())
+    }
+
+    /// Update dynamic reward parameters using a rolling window average.
+    /// The function takes the recent trade reward, current market volatility, and order book gap.
+    pub fn update_dynamic_reward(
+        ctx: Context<UpdateDynamicReward>,
+        recent_reward: u64,
+        market_volatility: u64,
+        order_book_gap: u64,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        // Update rolling average using recent_reward.
+        state.reward_sum = state.reward_sum.checked_add(recent_reward).unwrap();
+        state.reward_count = state.reward_count.checked_add(1).unwrap();
+        // When the window is complete, update the global multiplier.
+        if state.reward_count >= 50 {
+            let average = state.reward_sum / state.reward_count;
+            // Adjust multiplier based on market conditions.
+            // For example, boost multiplier by 10% if volatility is high or order book gap is wide.
+            let volatility_bonus = if market_volatility > 1000 { 110 } else { 100 };
+            let gap_bonus = if order_book_gap > 500 { 105 } else { 100 };
+            state.global_reward_multiplier = (average * volatility_bonus * gap_bonus) / (100 * 100);
+            state.reward_sum = 0;
+            state.reward_count = 0;
+        }
+        Ok(())
+    }
+
+    /// Create the caller's leaderboard stats. They are only ever updated by record_trade.
+    pub fn init_leaderboard(ctx: Context<InitLeaderboard>) -> Result<()> {
+        let leaderboard = &mut ctx.accounts.leaderboard;
+        leaderboard.user = ctx.accounts.user.key();
+        leaderboard.season = ctx.accounts.state.leaderboard_season;
+        leaderboard.bump = ctx.bumps.leaderboard;
+        Ok(())
+    }
+
+    /// Create the global top-N ranking by rolling 24h volume and velocity score.
+    pub fn init_leaderboard_top_n(ctx: Context<InitLeaderboardTopN>) -> Result<()> {
+        let mut top_n = ctx.accounts.leaderboard_top_n.load_init()?;
+        top_n.state = ctx.accounts.state.key();
+        top_n.season = ctx.accounts.state.leaderboard_season;
+        top_n.prize_curve_bps = DEFAULT_PRIZE_CURVE_BPS;
+        top_n.bump = ctx.bumps.leaderboard_top_n;
+        Ok(())
+    }
+
+    /// Admin: set the share of each window's prize vault paid to ranks 1..=16. Shares may sum
+    /// to less than 100%; the rest rolls into the next window.
+    pub fn set_prize_curve(ctx: Context<SetPrizeCurve>, prize_curve_bps: [u16; 16]) -> Result<()> {
+        let total: u64 = prize_curve_bps.iter().map(|&bps| bps as u64).sum();
+        require!(total <= BPS_DENOMINATOR, CustomError::InvalidPrizeCurve);
+        ctx.accounts.leaderboard_top_n.load_mut()?.prize_curve_bps = prize_curve_bps;
+        Ok(())
+    }
+
+    /// Pay the strategy boost a user earned in a finished epoch. record_trade does this on the
+    /// user's next trade; this lets anyone settle it for a user who stopped trading.
+    pub fn settle_strategy_boost(ctx: Context<SettleStrategyBoost>) -> Result<()> {
+        let epoch = ctx.accounts.state.reward_epoch;
+        let user_state = &mut ctx.accounts.user_state;
+        let settled_epoch = user_state.strategy_epoch;
+        let reputation = user_state.reputation_bps(Clock::get()?.unix_timestamp);
+        let (flags, boost) = user_state.settle_strategy(epoch);
+        let boost = bps_of(boost, reputation);
+        if boost > 0 {
+            user_state.accrued_rewards = user_state.accrued_rewards.checked_add(boost).unwrap();
+            emit!(StrategyBoostApplied {
+                user: user_state.owner,
+                epoch: settled_epoch,
+                flags,
+                boost,
+            });
+        }
+        Ok(())
+    }
+
+    /// Admin: start market-maker quoting rewards on a market. Quote snapshots come from
+    /// `attester`; the per-epoch budget is set by governance and starts at zero.
+    pub fn init_mm_program(
+        ctx: Context<InitMmProgram>,
+        attester: Pubkey,
+        max_spread_bps: u16,
+        depth_band_bps: u16,
+        snapshot_interval: i64,
+    ) -> Result<()> {
+        require!(max_spread_bps > 0 && depth_band_bps > 0 && snapshot_interval > 0, CustomError::InvalidMmParams);
+        let mm_program = &mut ctx.accounts.mm_program;
+        mm_program.state = ctx.accounts.state.key();
+        mm_program.market = ctx.accounts.market.key();
+        mm_program.attester = attester;
+        mm_program.max_spread_bps = max_spread_bps;
+        mm_program.depth_band_bps = depth_band_bps;
+        mm_program.snapshot_interval = snapshot_interval;
+        mm_program.epoch = ctx.accounts.state.reward_epoch;
+        mm_program.bump = ctx.bumps.mm_program;
+        Ok(())
+    }
+
+    /// Admin: change the attester and scoring parameters. Scores already recorded are kept.
+    pub fn update_mm_program(
+        ctx: Context<UpdateMmProgram>,
+        attester: Pubkey,
+        max_spread_bps: u16,
+        depth_band_bps: u16,
+        snapshot_interval: i64,
+    ) -> Result<()> {
+        require!(max_spread_bps > 0 && depth_band_bps > 0 && snapshot_interval > 0, CustomError::InvalidMmParams);
+        let mm_program = &mut ctx.accounts.mm_program;
+        mm_program.attester = attester;
+        mm_program.max_spread_bps = max_spread_bps;
+        mm_program.depth_band_bps = depth_band_bps;
+        mm_program.snapshot_interval = snapshot_interval;
+        Ok(())
+    }
+
+    /// Create a market maker's quote score on a market. Permissionless; the attester decides
+    /// whose quotes are snapshotted.
+    pub fn init_mm_score(ctx: Context<InitMmScore>, maker: Pubkey) -> Result<()> {
+        let mm_score = &mut ctx.accounts.mm_score;
+        mm_score.market = ctx.accounts.mm_program.market;
+        mm_score.maker = maker;
+        mm_score.epoch = ctx.accounts.state.reward_epoch;
+        mm_score.bump = ctx.bumps.mm_score;
+        Ok(())
+    }
+
+    /// Attester: record one snapshot of a maker's best quotes and the size they rest within
+    /// depth_band_bps of mid on each side. One snapshot per maker per snapshot_interval; a maker
+    /// not quoting is submitted with zero prices so the snapshot still counts against uptime.
+    pub fn submit_quote_snapshot(
+        ctx: Context<SubmitQuoteSnapshot>,
+        bid: u64,
+        ask: u64,
+        mid: u64,
+        bid_depth: u64,
+        ask_depth: u64,
+    ) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let epoch = ctx.accounts.state.reward_epoch;
+        let mm_program = &mut ctx.accounts.mm_program;
+        let mm_score = &mut ctx.accounts.mm_score;
+        mm_program.roll(epoch);
+        mm_score.roll(epoch);
+
+        let round = current_time / mm_program.snapshot_interval;
+        require!(round > mm_score.last_round, CustomError::QuoteTooSoon);
+        let score = quote_score(bid, ask, mid, bid_depth, ask_depth, mm_program.max_spread_bps)?;
+        mm_score.last_round = round;
+        mm_score.snapshots = mm_score.snapshots.checked_add(1).unwrap();
+        if score > 0 {
+            mm_score.quoted = mm_score.quoted.checked_add(1).unwrap();
+            mm_score.score = mm_score.score.checked_add(score as u128).unwrap();
+            mm_program.epoch_score = mm_program.epoch_score.checked_add(score as u128).unwrap();
+        }
+        emit!(QuoteSnapshotRecorded {
+            market: mm_program.market,
+            maker: mm_score.maker,
+            round,
+            score,
+        });
+        Ok(())
+    }
+
+    /// Pay a maker their share of the last finished epoch's budget, pro rata to quote score,
+    /// from the traders/LP treasury bucket. Unclaimed shares expire once the maker is scored
+    /// in a later epoch.
+    pub fn claim_mm_reward(ctx: Context<ClaimMmReward>) -> Result<()> {
+        let epoch = ctx.accounts.state.reward_epoch;
+        let mm_program = &mut ctx.accounts.mm_program;
+        let mm_score = &mut ctx.accounts.mm_score;
+        mm_program.roll(epoch);
+        mm_score.roll(epoch);
+        require!(
+            mm_score.pending_score > 0 && mm_score.pending_epoch == mm_program.prev_epoch,
+            CustomError::NoMarketMakerReward
+        );
+        let reward = (mm_program.prev_budget as u128 * mm_score.pending_score / mm_program.prev_score) as u64;
+        mm_score.pending_score = 0;
+        if reward > 0 {
+            treasury_transfer(
+                &ctx.accounts.token_program,
+                &ctx.accounts.bucket_vault,
+                &ctx.accounts.maker_token_account,
+                &ctx.accounts.treasury_authority.to_account_info(),
+                &ctx.accounts.state.key(),
+                ctx.accounts.state.treasury_authority_bump,
+                reward,
+            )?;
+            let treasury_bucket = &mut ctx.accounts.treasury_bucket;
+            treasury_bucket.total_spent = treasury_bucket.total_spent.checked_add(reward).unwrap();
+        }
+        emit!(MarketMakerRewardClaimed {
+            market: mm_program.market,
+            maker: mm_score.maker,
+            epoch: mm_score.pending_epoch,
+            amount: reward,
+        });
+        Ok(())
+    }
+
+    /// Admin: point a market at the external order book it trades on, for gap measurement.
+    pub fn init_order_book_source(
+        ctx: Context<InitOrderBookSource>,
+        layout: BookLayout,
+        dex_program: Pubkey,
+        dex_market: Pubkey,
+        bids: Pubkey,
+        asks: Pubkey,
+    ) -> Result<()> {
+        let source = &mut ctx.accounts.order_book_source;
+        source.market = ctx.accounts.market.key();
+        source.layout = layout;
+        source.dex_program = dex_program;
+        source.dex_market = dex_market;
+        source.bids = bids;
+        source.asks = asks;
+        source.aged_seq = 0;
+        source.pending_seq = 0;
+        source.pending_since = 0;
+        source.bump = ctx.bumps.order_book_source;
+        Ok(())
+    }
+
+    /// Credit a maker for narrowing the order book: the gap is read from the book with and
+    /// without the maker's open orders, and each bps of reduction earns GAP_REWARD_PER_BPS,
+    /// scaled by the size resting at the improved prices up to GAP_FULL_SIZE_LOTS. Only orders
+    /// at least GAP_MIN_ORDER_AGE old count, so quotes flashed in around the call earn nothing.
+    /// Permissionless, at most once per GAP_CREDIT_INTERVAL per maker.
+    pub fn credit_gap_reduction(ctx: Context<CreditGapReduction>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let source = &mut ctx.accounts.order_book_source;
+        let user_state = &mut ctx.accounts.user_state;
+        let open_orders_owner = openbook_open_orders_owner(&ctx.accounts.open_orders.try_borrow_data()?, &source.dex_market)?;
+        require_keys_eq!(open_orders_owner, user_state.owner, CustomError::InvalidOrderBook);
+        require!(
+            current_time >= user_state.last_gap_credit.checked_add(GAP_CREDIT_INTERVAL).unwrap(),
+            CustomError::GapCreditTooSoon
+        );
+
+        let gap = gap_reduction(
+            source.layout,
+            &ctx.accounts.bids.try_borrow_data()?,
+            &ctx.accounts.asks.try_borrow_data()?,
+            &ctx.accounts.open_orders.key(),
+            source.aged_seq,
+        )?;
+        source.observe_seq(gap.latest_seq, current_time);
+        let (gap_before_bps, gap_after_bps) = (gap.before_bps, gap.after_bps);
+        let reduction = gap_before_bps.saturating_sub(gap_after_bps);
+        let full_reward = reduction.checked_mul(GAP_REWARD_PER_BPS).unwrap();
+        let sized_reward = (full_reward as u128 * gap.maker_size.min(GAP_FULL_SIZE_LOTS) as u128
+            / GAP_FULL_SIZE_LOTS as u128) as u64;
+        let reward = bps_of(sized_reward, user_state.reputation_bps(current_time));
+        user_state.last_gap_credit = current_time;
+        user_state.gap_credit_bps = user_state.gap_credit_bps.checked_add(reduction).unwrap();
+        user_state.accrued_rewards = user_state.accrued_rewards.checked_add(reward).unwrap();
+        emit!(GapReductionCredited {
+            market: source.market,
+            maker: user_state.owner,
+            gap_before_bps,
+            gap_after_bps,
+            reward,
+        });
+        Ok(())
+    }
+
+    /// Admin: set the venue that signs the fills passed to record_trade.
+    pub fn set_trade_venue(ctx: Context<SetTradeVenue>, trade_venue: Pubkey) -> Result<()> {
+        ctx.accounts.state.trade_venue = trade_venue;
+        Ok(())
+    }
+
+    /// Admin: register the VRF program whose result accounts may seed batch shuffles.
+    pub fn set_vrf_program(ctx: Context<SetVrfProgram>, vrf_program: Pubkey) -> Result<()> {
+        ctx.accounts.state.vrf_program = vrf_program;
+        Ok(())
+    }
+
+    /// First half of a randomized batch: commit to H(seed) for a batch of `item_count` trades.
+    /// The randomness source is fixed now, before any of its output for later slots exists.
+    pub fn commit_shuffle_seed(
+        ctx: Context<CommitShuffleSeed>,
+        item_count: u16,
+        seed_commitment: [u8; 32],
+        source: RandomnessSource,
+        vrf_account: Pubkey,
+    ) -> Result<()> {
+        require!(
+            item_count > 0 && item_count as usize <= ShuffleBatch::MAX_ITEMS,
+            CustomError::InvalidShuffleBatch
+        );
+        let state = &mut ctx.accounts.state;
+        let shuffle_batch = &mut ctx.accounts.shuffle_batch;
+        shuffle_batch.state = state.key();
+        shuffle_batch.batch_id = state.shuffle_batch_count;
+        shuffle_batch.committer = ctx.accounts.committer.key();
+        shuffle_batch.item_count = item_count;
+        shuffle_batch.seed_commitment = seed_commitment;
+        shuffle_batch.commit_slot = Clock::get()?.slot;
+        shuffle_batch.source = source;
+        shuffle_batch.vrf_account = vrf_account;
+        shuffle_batch.randomness = [0; 32];
+        shuffle_batch.permutation = Vec::new();
+        shuffle_batch.processed = false;
+        shuffle_batch.bump = ctx.bumps.shuffle_batch;
+        state.shuffle_batch_count = state.shuffle_batch_count.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    /// Randomized batch processing to further prevent front-running.
+    /// Reveals the committed seed and mixes it with randomness produced after the commit slot
+    /// (the hash of the first slot after the commit, or a VRF result), then writes the shuffled
+    /// execution order to the batch.
+    pub fn batch_process_trades(ctx: Context<BatchProcessTrades>, seed: [u8; 32]) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let shuffle_batch = &mut ctx.accounts.shuffle_batch;
+        require!(!shuffle_batch.processed, CustomError::ShuffleAlreadyProcessed);
+        require!(
+            hashv(&[seed.as_ref()]).to_bytes() == shuffle_batch.seed_commitment,
+            CustomError::CommitmentMismatch
+        );
+        require!(
+            Clock::get()?.slot > shuffle_batch.commit_slot,
+            CustomError::RandomnessNotAvailable
+        );
+
+        let entropy = match shuffle_batch.source {
+            RandomnessSource::SlotHashes => {
+                let data = ctx.accounts.slot_hashes.try_borrow_data()?;
+                read_slot_hash_after(&data, shuffle_batch.commit_slot)?
+            }
+            RandomnessSource::Vrf => {
+                let vrf = ctx
+                    .accounts
+                    .vrf_randomness
+                    .as_ref()
+                    .ok_or(CustomError::InvalidRandomnessAccount)?;
+                require!(
+                    vrf.key() == shuffle_batch.vrf_account && *vrf.owner == state.vrf_program,
+                    CustomError::InvalidRandomnessAccount
+                );
+                let data = vrf.try_borrow_data()?;
+                read_vrf_randomness(&data, shuffle_batch.commit_slot)?
+            }
+        };
+        let randomness = hashv(&[seed.as_ref(), entropy.as_ref(), shuffle_batch.key().as_ref()]).to_bytes();
+        shuffle_batch.randomness = randomness;
+        shuffle_batch.permutation = shuffled_indices(shuffle_batch.item_count, &randomness);
+        shuffle_batch.processed = true;
+
+        emit!(ShuffleBatchProcessed {
+            batch_id: shuffle_batch.batch_id,
+            randomness,
+            permutation: shuffle_batch.permutation.clone(),
+        });
+        Ok(())
+    }
+
+    /// Create the keeper registry and its bounty vault. The vault is funded by transfers, e.g. a
+    /// governance spend from the ecosystem bucket. Bounties start at zero until configured.
+    pub fn init_keeper_registry(ctx: Context<InitKeeperRegistry>) -> Result<()> {
+        let keeper_registry = &mut ctx.accounts.keeper_registry;
+        keeper_registry.state = ctx.accounts.state.key();
+        keeper_registry.vault = ctx.accounts.keeper_vault.key();
+        keeper_registry.bounties = [0; 5];
+        keeper_registry.intervals = KeeperRegistry::DEFAULT_INTERVALS;
+        keeper_registry.last_run = [0; 5];
+        keeper_registry.total_paid = 0;
+        keeper_registry.treasury_authority_bump = ctx.accounts.state.treasury_authority_bump;
+        keeper_registry.bump = ctx.bumps.keeper_registry;
+        Ok(())
+    }
+
+    /// Admin: set the bounty (LVT base units) and minimum interval (seconds) of one crank.
+    pub fn set_keeper_crank(ctx: Context<SetKeeperCrank>, kind: CrankKind, bounty: u64, interval: i64) -> Result<()> {
+        require!(interval >= 0, CustomError::InvalidDelay);
+        let keeper_registry = &mut ctx.accounts.keeper_registry;
+        keeper_registry.bounties[kind as usize] = bounty;
+        keeper_registry.intervals[kind as usize] = interval;
+        Ok(())
+    }
+
+    /// Allow LVT token holders to borrow against their staked LVT.
+    /// This is a simplified example of DeFi lending integration.
+    pub fn borrow_against_lvt(ctx: Context<BorrowAgainstLVT>, borrow_amount: u64) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        // Ensure the user’s staked collateral is at least 150% of the borrow_amount.
+        require!(
+            collateral_covers(user_state.staked_amount, borrow_amount),
+            CustomError::InsufficientCollateral
+        );
+        // Initialize a loan account with a fixed interest rate and due time.
+        let loan = &mut ctx.accounts.loan_account;
+        loan.borrower = user_state.owner;
+        loan.collateral = user_state.staked_amount;
+        loan.borrow_amount = borrow_amount;
+        loan.interest_rate = 5; // Example: 5%
+        loan.start_time = Clock::get()?.unix_timestamp;
+        loan.due_time = Clock::get()?.unix_timestamp + 30 * 86400; // Due in 30 days
+        loan.liquidated = false;
+        Ok(())
+    }
+
+    /// Keeper crank: liquidate a loan that is past due or whose borrower's stake has fallen below
+    /// 150% of the borrowed amount. Borrowing moves no tokens, so this settles the loan's books.
+    pub fn liquidate_loan(ctx: Context<LiquidateLoan>) -> Result<()> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let loan = &mut ctx.accounts.loan_account;
+        require!(!loan.liquidated, CustomError::LoanNotLiquidatable);
+        let undercollateralized = !collateral_covers(ctx.accounts.user_state.staked_amount, loan.borrow_amount);
+        require!(
+            current_time > loan.due_time || undercollateralized,
+            CustomError::LoanNotLiquidatable
+        );
+        loan.liquidated = true;
+        emit!(LoanLiquidated {
+            loan: loan.key(),
+            borrower: loan.borrower,
+            borrow_amount: loan.borrow_amount,
+        });
+        ctx.accounts.keeper.pay(CrankKind::LoanLiquidation, current_time)
+    }
+}
+
+//
+// CONSTANTS
+//
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// Treasury buckets, in the order of `State.treasury_split_bps`.
+pub const TREASURY_BUCKET_COUNT: usize = 4;
+pub const BUCKET_TRADERS_LPS: u8 = 0;
+pub const BUCKET_STAKING: u8 = 1;
+pub const BUCKET_DEVELOPMENT: u8 = 2;
+pub const BUCKET_ECOSYSTEM: u8 = 3;
+
+// Fee rates (State.fee_rate, Market.fee_rate) are expressed in millionths: 1000 = 0.1%.
+pub const FEE_RATE_DENOMINATOR: u64 = 1_000_000;
+
+// Fixed-point precision of StakingPool.acc_reward_per_share.
+pub const ACC_REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+// Leaderboard stats cover a rolling window of this many hourly buckets.
+pub const LEADERBOARD_HOURS: usize = 24;
+
+// Share of a leaderboard window's prize vault paid to each rank, best first.
+pub const DEFAULT_PRIZE_CURVE_BPS: [u16; 16] =
+    [3000, 2000, 1500, 1000, 700, 500, 400, 300, 200, 150, 100, 50, 50, 25, 15, 10];
+
+// Strategy classification, evaluated per reward epoch (see classify_strategy).
+pub const STRATEGY_MARKET_MAKER: u8 = 1 << 0;
+pub const STRATEGY_ARBITRAGEUR: u8 = 1 << 1;
+pub const MM_MIN_MAKER_SHARE_BPS: u64 = 6_000; // Maker volume / epoch volume.
+pub const MM_MIN_FILLS_PER_SIDE: u32 = 5;
+pub const MM_BOOST_BPS: u64 = 10; // Of maker volume.
+pub const MM_BOOST_CAP: u64 = 50_000;
+pub const ARB_MIN_GAP_BPS: u64 = 10; // Minimum price gap to the reference market.
+pub const ARB_MIN_SHARE_BPS: u64 = 5_000; // Converging volume / epoch volume.
+pub const ARB_MIN_TRADES: u32 = 5;
+pub const ARB_BOOST_BPS: u64 = 20; // Of converging volume.
+pub const ARB_BOOST_CAP: u64 = 100_000;
+
+// OpenBook (Serum v3) accounts: 5-byte "serum" head, u64 account flags, body, 7-byte "padding" tail.
+pub const OPENBOOK_HEAD_LEN: usize = 5 + 8;
+pub const OPENBOOK_TAIL_LEN: usize = 7;
+pub const OPENBOOK_FLAG_INITIALIZED: u64 = 1 << 0;
+pub const OPENBOOK_FLAG_OPEN_ORDERS: u64 = 1 << 2;
+pub const OPENBOOK_FLAG_BIDS: u64 = 1 << 5;
+pub const OPENBOOK_FLAG_ASKS: u64 = 1 << 6;
+// Slab: 32-byte header, then 72-byte nodes (u32 tag + 68 bytes); leaf keys are price << 64 | seq.
+pub const SLAB_HEADER_LEN: usize = 32;
+pub const SLAB_NODE_LEN: usize = 72;
+pub const SLAB_TAG_LEAF: u32 = 2;
+
+// Wash trade signals (TradeRecord.wash_flags).
+pub const WASH_DECLARED_LINK: u8 = 1 << 0;
+pub const WASH_FLAGGED_LINK: u8 = 1 << 1;
+pub const WASH_REPEAT_COUNTERPARTY: u8 = 1 << 2;
+pub const WASH_ROUND_TRIP: u8 = 1 << 3;
+pub const WASH_WINDOW: i64 = 3600; // Trades with one counterparty further apart than this are unrelated.
+pub const WASH_REPEAT_LIMIT: u32 = 5; // Trades with one counterparty allowed within the window.
+pub const COUNTERPARTY_LRU_SIZE: usize = 4;
+
+// Batch auctions: commitments are bonded in LVT, refunded once revealed and forfeited otherwise.
+pub const AUCTION_MIN_BOND: u64 = 1_000;
+pub const AUCTION_BOND_BPS: u64 = 100; // Of the revealed order's notional, price * size.
+
+// Reputation: penalties in bps of a full reputation, recovering linearly over time.
+pub const WASH_PENALTY_BPS: u64 = 1_000;
+pub const CANCEL_SPAM_PENALTY_BPS: u64 = 100; // Per cancel beyond CANCEL_SPAM_LIMIT in the window.
+pub const UNREVEALED_COMMITMENT_PENALTY_BPS: u64 = 500;
+pub const CANCEL_SPAM_WINDOW: i64 = 3600;
+pub const CANCEL_SPAM_LIMIT: u32 = 20;
+pub const REPUTATION_RECOVERY_BPS_PER_DAY: u64 = 500;
+pub const LEADERBOARD_MIN_REPUTATION_BPS: u64 = 8_000;
+
+// Order book gap credit: a one-sided or empty book counts as a MAX_BOOK_GAP_BPS gap.
+pub const MAX_BOOK_GAP_BPS: u64 = 1_000;
+pub const GAP_CREDIT_INTERVAL: i64 = 60;
+pub const GAP_REWARD_PER_BPS: u64 = 10;
+pub const GAP_FULL_SIZE_LOTS: u64 = 1_000; // Resting size at the improved prices for the full reward.
+pub const GAP_MIN_ORDER_AGE: i64 = 30; // Seconds an order must have rested to count.
+
+// veLVT: a lock of VE_MAX_LOCK seconds (the longest allowed) gives one vote per staked token.
+pub const VE_MAX_LOCK: i64 = 180 * 86400;
+pub const VE_MAX_BOOST_BPS: u64 = 5_000; // +50% rewards with enough veLVT for the volume traded.
+pub const VE_BOOST_COVERAGE_BPS: u64 = 1_000; // veLVT per epoch volume needed for the full boost.
+
+// Governance: longest voting period; stake checkpoints are kept at least this long.
+pub const MAX_VOTING_PERIOD: i64 = 14 * 86400;
+// Stake history keeps one checkpoint per period; proposals read the end of the previous one.
+pub const STAKE_CHECKPOINT_PERIOD: i64 = 86400;
+
+//
+// HELPER FUNCTIONS
+//
+
+fn bps_of(amount: u64, bps: u64) -> u64 {
+    ((amount as u128) * (bps as u128) / (BPS_DENOMINATOR as u128)) as u64
+}
+
+/// Move LVT out of a program-owned vault, signing with the treasury authority PDA.
+fn treasury_transfer<'info>(
+    token_program: &Program<'info, Token>,
+    from: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    treasury_authority: &AccountInfo<'info>,
+    state_key: &Pubkey,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let seeds: &[&[u8]] = &[b"treasury", state_key.as_ref(), &[bump]];
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            token::Transfer {
+                from: from.to_account_info(),
+                to: to.to_account_info(),
+                authority: treasury_authority.clone(),
+            },
+            &[seeds],
+        ),
+        amount,
+    )
+}
+
+/// Checks a tier table independent of the mint: bounded length, sane bps, ascending minimum stake.
+fn validate_tier_params(params: &[TierParams]) -> Result<()> {
+    require!(
+        !params.is_empty() && params.len() <= TierConfig::MAX_TIERS,
+        CustomError::InvalidTierConfig
+    );
+    for (i, p) in params.iter().enumerate() {
+        require!(
+            p.fee_discount_bps as u64 <= BPS_DENOMINATOR
+                && p.rebate_bps as u64 <= BPS_DENOMINATOR
+                && p.boost_multiplier_bps > 0,
+            CustomError::InvalidTierConfig
+        );
+        if i > 0 {
+            require!(
+                p.min_stake_tokens > params[i - 1].min_stake_tokens,
+                CustomError::InvalidTierConfig
+            );
+        }
+    }
+    Ok(())
+}
+
+fn compute_reward_multiplier(accrued: u64, trade_count: u64) -> u64 {
+    if trade_count == 0 { 1 } else { accrued / trade_count }
+}
+
+/// Duration-based yield multiplier for a stake position (README: 1, 3 or 6 month lockups).
+fn lockup_yield_multiplier_bps(lockup_duration: i64) -> u64 {
+    if lockup_duration >= 180 * 86400 {
+        15_000 // 6 months: 1.5x
+    } else if lockup_duration >= 90 * 86400 {
+        12_500 // 3 months: 1.25x
+    } else if lockup_duration >= 30 * 86400 {
+        11_000 // 1 month: 1.1x
+    } else {
+        BPS_DENOMINATOR
+    }
+}
+
+/// Effective fee for a trade. Institutional accounts pay their negotiated schedule instead of the
+/// market rate and tier discount; everyone gets active promotions, and makers get their tier rebate.
+fn compute_fee_quote(
+    state: &State,
+    market: &Market,
+    user_state: &UserState,
+    institutional_profile: Option<&InstitutionalProfile>,
+    notional: u64,
+    side: LiquiditySide,
+    now: i64,
+) -> FeeQuote {
+    let market_rate = if market.fee_rate == 0 { state.fee_rate } else { market.fee_rate };
+    // A live profile's negotiated rate wins over the market's standard institutional schedule.
+    let negotiated_rate = institutional_profile
+        .filter(|profile| user_state.is_institutional && profile.is_active(now) && profile.fee_rate > 0)
+        .map(|profile| profile.fee_rate);
+    let institutional_rate = match negotiated_rate {
+        Some(rate) => Some(rate),
+        None if user_state.is_institutional && market.institutional_fee_rate > 0 => {
+            Some(market.institutional_fee_rate)
+        }
+        None => None,
+    };
+    let institutional = institutional_rate.is_some();
+    let fee_rate = institutional_rate.unwrap_or(market_rate);
+    let base_fee = ((notional as u128) * (fee_rate as u128) / (FEE_RATE_DENOMINATOR as u128)) as u64;
+
+    let mut fee = base_fee;
+    if !institutional {
+        fee -= bps_of(fee, user_state.fee_discount);
+    }
+    if market.promotion_active(now) {
+        fee -= bps_of(fee, market.promo_discount_bps as u64);
+    }
+    let rebate = match side {
+        LiquiditySide::Maker => bps_of(fee, user_state.trading_rebate),
+        LiquiditySide::Taker => 0,
+    };
+    FeeQuote {
+        fee_rate,
+        base_fee,
+        discount: base_fee - fee,
+        rebate,
+        effective_fee: fee - rebate,
+    }
+}
+
+/// Deterministic batch order: execution priority first, then stake score, then arrival (order id).
+fn sequence_cmp(a: &QueuedOrder, b: &QueuedOrder) -> std::cmp::Ordering {
+    b.priority
+        .cmp(&a.priority)
+        .then(b.stake_score.cmp(&a.stake_score))
+        .then(a.id.cmp(&b.id))
+}
+
+fn sequence_orders(orders: &mut [QueuedOrder]) {
+    orders.sort_by(sequence_cmp);
+}
+
+/// Bond a revealed order must have escrowed: AUCTION_BOND_BPS of its notional, at least
+/// AUCTION_MIN_BOND.
+fn auction_bond_required(price: u64, size: u64) -> u64 {
+    let notional = price as u128 * size as u128;
+    let bond = notional.saturating_mul(AUCTION_BOND_BPS as u128) / BPS_DENOMINATOR as u128;
+    bond.min(u64::MAX as u128).max(AUCTION_MIN_BOND as u128) as u64
+}
+
+fn order_commitment_hash(
+    auction: &Pubkey,
+    owner: &Pubkey,
+    side: OrderSide,
+    price: u64,
+    size: u64,
+    salt: &[u8; 32],
+) -> [u8; 32] {
+    hashv(&[
+        auction.as_ref(),
+        owner.as_ref(),
+        &[side as u8],
+        price.to_le_bytes().as_ref(),
+        size.to_le_bytes().as_ref(),
+        salt.as_ref(),
+    ])
+    .to_bytes()
+}
+
+/// Uniform price that maximises matched volume (ties: smallest imbalance, then lowest price).
+/// Returns (price, volume); volume is 0 when the book does not cross.
+fn uniform_clearing_price(orders: &[AuctionOrder]) -> (u64, u64) {
+    let mut best = (0u64, 0u64, u64::MAX); // (price, volume, imbalance)
+    for candidate in orders.iter().map(|o| o.order.price) {
+        let demand: u64 = orders
+            .iter()
+            .filter(|o| o.order.side == OrderSide::Bid && o.order.price >= candidate)
+            .map(|o| o.order.size)
+            .sum();
+        let supply: u64 = orders
+            .iter()
+            .filter(|o| o.order.side == OrderSide::Ask && o.order.price <= candidate)
+            .map(|o| o.order.size)
+            .sum();
+        let volume = demand.min(supply);
+        let imbalance = demand.abs_diff(supply);
+        let better = volume > best.1
+            || (volume == best.1 && volume > 0 && (imbalance, candidate) < (best.2, best.0));
+        if better {
+            best = (candidate, volume, imbalance);
+        }
+    }
+    (best.0, best.1)
+}
+
+/// Fill one side of a settled auction up to `volume`: best price first, then sequence order.
+fn allocate_auction_fills(orders: &mut [AuctionOrder], side: OrderSide, clearing_price: u64, volume: u64) {
+    let mut eligible: Vec<usize> = (0..orders.len())
+        .filter(|&i| {
+            let order = &orders[i].order;
+            order.side == side
+                && match side {
+                    OrderSide::Bid => order.price >= clearing_price,
+                    OrderSide::Ask => order.price <= clearing_price,
+                }
+        })
+        .collect();
+    eligible.sort_by(|&i, &j| {
+        let (a, b) = (&orders[i].order, &orders[j].order);
+        let by_price = match side {
+            OrderSide::Bid => b.price.cmp(&a.price),
+            OrderSide::Ask => a.price.cmp(&b.price),
+        };
+        by_price.then(sequence_cmp(a, b))
+    });
+    let mut remaining = volume;
+    for i in eligible {
+        let fill = orders[i].order.size.min(remaining);
+        orders[i].filled = fill;
+        remaining -= fill;
+    }
+}
+
+/// Hash chain over sequenced batches: H(previous hash || batch id || order ids in execution order).
+fn batch_sequence_hash(previous: &[u8; 32], batch_id: u64, order_ids: &[u64]) -> [u8; 32] {
+    let ids: Vec<u8> = order_ids.iter().flat_map(|id| id.to_le_bytes()).collect();
+    hashv(&[previous.as_ref(), batch_id.to_le_bytes().as_ref(), ids.as_ref()]).to_bytes()
+}
+
+/// Hash of the first slot produced after the commit, read from the SlotHashes sysvar (u64 count,
+/// then (slot, hash) pairs, newest first). The slot is fixed by the commit, so processing the batch
+/// later cannot pick a different hash; once that slot has been evicted the batch cannot be processed.
+fn read_slot_hash_after(data: &[u8], commit_slot: u64) -> Result<[u8; 32]> {
+    require!(data.len() >= 8, CustomError::RandomnessNotAvailable);
+    let count = u64::from_le_bytes(data[0..8].try_into().unwrap()) as usize;
+    let mut first_after: Option<(u64, [u8; 32])> = None;
+    for entry in data[8..].chunks_exact(8 + 32).take(count) {
+        let slot = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+        if slot <= commit_slot {
+            // Every slot after the commit is newer than this entry, so `first_after` is final.
+            return first_after
+                .map(|(_, hash)| hash)
+                .ok_or(error!(CustomError::RandomnessNotAvailable));
+        }
+        first_after = Some((slot, entry[8..40].try_into().unwrap()));
+    }
+    match first_after {
+        Some((slot, hash)) if slot == commit_slot + 1 => Ok(hash),
+        // Older entries were evicted, so an earlier slot after the commit may have been dropped.
+        Some(_) => err!(CustomError::RandomnessExpired),
+        None => err!(CustomError::RandomnessNotAvailable),
+    }
+}
+
+/// VRF result account layout: 8-byte discriminator, 32 bytes of randomness, u64 slot it was fulfilled at.
+fn read_vrf_randomness(data: &[u8], commit_slot: u64) -> Result<[u8; 32]> {
+    require!(data.len() >= 8 + 32 + 8, CustomError::InvalidRandomnessAccount);
+    let fulfilled_slot = u64::from_le_bytes(data[40..48].try_into().unwrap());
+    require!(fulfilled_slot > commit_slot, CustomError::RandomnessNotAvailable);
+    Ok(data[8..40].try_into().unwrap())
+}
+
+/// Short key identifying a trading pair in the counterparty LRU.
+fn pair_key(trade_pair: &str) -> [u8; 8] {
+    hashv(&[trade_pair.as_bytes()]).to_bytes()[..8].try_into().unwrap()
+}
+
+/// WASH_* link signals between two wallets, read from their WalletLink PDA if it exists.
+fn read_wallet_link_flags(wallet_link: &AccountInfo, user: &Pubkey, counterparty: &Pubkey, program_id: &Pubkey) -> Result<u8> {
+    let (wallet_a, wallet_b) = if user < counterparty { (user, counterparty) } else { (counterparty, user) };
+    let (expected, _) = Pubkey::find_program_address(&[b"wallet_link", wallet_a.as_ref(), wallet_b.as_ref()], program_id);
+    require_keys_eq!(wallet_link.key(), expected, CustomError::InvalidWalletLink);
+    if wallet_link.owner != program_id || wallet_link.data_is_empty() {
+        return Ok(0);
+    }
+    let link = WalletLink::try_deserialize(&mut &wallet_link.try_borrow_data()?[..])?;
+    let mut flags = 0;
+    if link.declared {
+        flags |= WASH_DECLARED_LINK;
+    }
+    if link.flagged {
+        flags |= WASH_FLAGGED_LINK;
+    }
+    Ok(flags)
+}
+
+/// A resting order read from an OpenBook slab leaf.
+struct SlabLeaf {
+    price: u64,
+    seq: u64,
+    owner: Pubkey,
+    quantity: u64,
+}
+
+/// Resting orders on one side of an OpenBook slab.
+fn openbook_leaves(data: &[u8], bids: bool) -> Result<Vec<SlabLeaf>> {
+    require!(data.len() >= OPENBOOK_HEAD_LEN + SLAB_HEADER_LEN + OPENBOOK_TAIL_LEN, CustomError::InvalidOrderBook);
+    let flags = u64::from_le_bytes(data[5..13].try_into().unwrap());
+    let side_flag = if bids { OPENBOOK_FLAG_BIDS } else { OPENBOOK_FLAG_ASKS };
+    require!(flags == OPENBOOK_FLAG_INITIALIZED | side_flag, CustomError::InvalidOrderBook);
+    let slab = &data[OPENBOOK_HEAD_LEN..data.len() - OPENBOOK_TAIL_LEN];
+    // Every node below bump_index is in use or on the free list; leaves are the resting orders.
+    let bump_index = u64::from_le_bytes(slab[0..8].try_into().unwrap()) as usize;
+    require!(
+        bump_index <= (slab.len() - SLAB_HEADER_LEN) / SLAB_NODE_LEN,
+        CustomError::InvalidOrderBook
+    );
+    let mut leaves = Vec::new();
+    for node in slab[SLAB_HEADER_LEN..].chunks_exact(SLAB_NODE_LEN).take(bump_index) {
+        if u32::from_le_bytes(node[0..4].try_into().unwrap()) != SLAB_TAG_LEAF {
+            continue;
+        }
+        // Leaf: tag, owner_slot u8, fee_tier u8, 2 padding, key u128, owner, quantity, client_order_id.
+        // The key is price << 64 | seq, with the sequence number inverted on bids.
+        let key = u128::from_le_bytes(node[8..24].try_into().unwrap());
+        let seq = if bids { !(key as u64) } else { key as u64 };
+        leaves.push(SlabLeaf {
+            price: (key >> 64) as u64,
+            seq,
+            owner: Pubkey::new_from_array(node[24..56].try_into().unwrap()),
+            quantity: u64::from_le_bytes(node[56..64].try_into().unwrap()),
+        });
+    }
+    Ok(leaves)
+}
+
+/// Best price among `leaves`: highest bid or lowest ask.
+fn best_leaf_price<'a>(leaves: impl Iterator<Item = &'a SlabLeaf>, bids: bool) -> Option<u64> {
+    let prices = leaves.map(|leaf| leaf.price);
+    if bids {
+        prices.max()
+    } else {
+        prices.min()
+    }
+}
+
+/// Owner of an OpenBook open orders account on `dex_market`.
+fn openbook_open_orders_owner(data: &[u8], dex_market: &Pubkey) -> Result<Pubkey> {
+    require!(data.len() >= OPENBOOK_HEAD_LEN + 64 + OPENBOOK_TAIL_LEN, CustomError::InvalidOrderBook);
+    let flags = u64::from_le_bytes(data[5..13].try_into().unwrap());
+    require!(
+        flags == OPENBOOK_FLAG_INITIALIZED | OPENBOOK_FLAG_OPEN_ORDERS
+            && data[13..45] == dex_market.to_bytes(),
+        CustomError::InvalidOrderBook
+    );
+    Ok(Pubkey::new_from_array(data[45..77].try_into().unwrap()))
+}
+
+/// Best bid/ask gap in bps of mid, capped at MAX_BOOK_GAP_BPS. A side without orders is a full gap.
+fn book_gap_bps(best_bid: Option<u64>, best_ask: Option<u64>) -> u64 {
+    match (best_bid, best_ask) {
+        (Some(bid), Some(ask)) if ask > bid => {
+            let mid = (bid as u128 + ask as u128) / 2;
+            ((ask - bid) as u128 * BPS_DENOMINATOR as u128 / mid).min(MAX_BOOK_GAP_BPS as u128) as u64
+        }
+        (Some(_), Some(_)) => 0,
+        _ => MAX_BOOK_GAP_BPS,
+    }
+}
+
+/// A maker's effect on the book gap.
+#[derive(Debug, PartialEq, Eq)]
+struct BookGap {
+    before_bps: u64, // Without the maker's orders.
+    after_bps: u64,  // With the maker's aged orders.
+    maker_size: u64, // Aged maker size resting at prices better than everyone else's, both sides.
+    latest_seq: u64, // Newest order sequence number seen in the book.
+}
+
+/// Gap of the book without and with one open orders account's orders. Only maker orders with a
+/// sequence number up to `aged_seq` count.
+fn gap_reduction(layout: BookLayout, bids: &[u8], asks: &[u8], open_orders: &Pubkey, aged_seq: u64) -> Result<BookGap> {
+    match layout {
+        BookLayout::OpenBookSlab => {
+            let mut best_before = [None; 2];
+            let mut best_after = [None; 2];
+            let mut maker_size = 0u64;
+            let mut latest_seq = 0u64;
+            for (i, (data, is_bids)) in [(bids, true), (asks, false)].into_iter().enumerate() {
+                let leaves = openbook_leaves(data, is_bids)?;
+                latest_seq = leaves.iter().map(|leaf| leaf.seq).fold(latest_seq, u64::max);
+                let counted = |leaf: &&SlabLeaf| leaf.owner != *open_orders || leaf.seq <= aged_seq;
+                let others = best_leaf_price(leaves.iter().filter(|leaf| leaf.owner != *open_orders), is_bids);
+                let improves = |price: u64| match others {
+                    Some(other) if is_bids => price > other,
+                    Some(other) => price < other,
+                    None => true,
+                };
+                for leaf in leaves.iter().filter(counted).filter(|leaf| leaf.owner == *open_orders) {
+                    if improves(leaf.price) {
+                        maker_size = maker_size.saturating_add(leaf.quantity);
+                    }
+                }
+                best_before[i] = others;
+                best_after[i] = best_leaf_price(leaves.iter().filter(counted), is_bids);
+            }
+            Ok(BookGap {
+                before_bps: book_gap_bps(best_before[0], best_before[1]),
+                after_bps: book_gap_bps(best_after[0], best_after[1]),
+                maker_size,
+                latest_seq,
+            })
+        }
+    }
+}
+
+/// Fisher-Yates shuffle of 0..count driven by a hash chain over `randomness`.
+fn shuffled_indices(count: u16, randomness: &[u8; 32]) -> Vec<u16> {
+    let mut indices: Vec<u16> = (0..count).collect();
+    for i in (1..indices.len()).rev() {
+        let draw = hashv(&[randomness.as_ref(), (i as u64).to_le_bytes().as_ref()]).to_bytes();
+        let j = (u64::from_le_bytes(draw[0..8].try_into().unwrap()) % (i as u64 + 1)) as usize;
+        indices.swap(i, j);
+    }
+    indices
+}
+
+/// Remove a position from the user's aggregate stake so it no longer counts for tiers or staking yield.
+fn detach_stake_position(user_state: &mut UserState, staking_pool: &mut StakingPool, position: &StakePosition) {
+    user_state.staked_amount = user_state.staked_amount.checked_sub(position.amount).unwrap();
+    user_state.remove_lock(position);
+    let new_weight = user_state
+        .weighted_stake
+        .checked_sub(position.weighted_amount())
+        .unwrap();
+    staking_pool.reweight(user_state, new_weight);
+}
+
+/// Re-insert `trader` into a descending ranking, dropping entries older than the 24h window.
+fn rerank(ranking: &mut [RankEntry; 16], trader: Pubkey, value: u64, hour: i64) {
+    let mut entries: Vec<RankEntry> = ranking
+        .iter()
+        .filter(|entry| {
+            entry.trader != Pubkey::default()
+                && entry.trader != trader
+                && hour - entry.hour < LEADERBOARD_HOURS as i64
+        })
+        .copied()
+        .collect();
+    if value > 0 {
+        entries.push(RankEntry { trader, value, hour });
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.value));
+    for (i, slot) in ranking.iter_mut().enumerate() {
+        *slot = entries.get(i).copied().unwrap_or_else(RankEntry::default);
+    }
+}
+
+/// A fill converges prices if it buys below, or sells above, the reference market by at least
+/// ARB_MIN_GAP_BPS.
+fn converges(side: OrderSide, price: u64, reference_price: u64) -> bool {
+    if price == 0 || reference_price == 0 {
+        return false;
+    }
+    let gap = price.abs_diff(reference_price) as u128 * BPS_DENOMINATOR as u128 / reference_price as u128;
+    gap >= ARB_MIN_GAP_BPS as u128
+        && match side {
+            OrderSide::Bid => price < reference_price,
+            OrderSide::Ask => price > reference_price,
+        }
+}
+
+/// Classify one epoch of strategy stats. Market makers provide most of their volume as maker
+/// on both sides; arbitrageurs mostly trade toward another market's price. Each earns a boost
+/// proportional to the qualifying volume, capped per epoch.
+fn classify_strategy(user_state: &UserState) -> (u8, u64) {
+    let share = |volume: u64| {
+        if user_state.epoch_volume == 0 { 0 } else { volume as u128 * BPS_DENOMINATOR as u128 / user_state.epoch_volume as u128 }
+    };
+    let mut flags = 0;
+    let mut boost = 0;
+    if share(user_state.maker_volume) >= MM_MIN_MAKER_SHARE_BPS as u128
+        && user_state.maker_bids.min(user_state.maker_asks) >= MM_MIN_FILLS_PER_SIDE
+    {
+        flags |= STRATEGY_MARKET_MAKER;
+        boost += bps_of(user_state.maker_volume, MM_BOOST_BPS).min(MM_BOOST_CAP);
+    }
+    if share(user_state.arb_volume) >= ARB_MIN_SHARE_BPS as u128 && user_state.arb_trades >= ARB_MIN_TRADES {
+        flags |= STRATEGY_ARBITRAGEUR;
+        boost += bps_of(user_state.arb_volume, ARB_BOOST_BPS).min(ARB_BOOST_CAP);
+    }
+    (flags, boost)
+}
+
+/// Score of one quote snapshot: the size quoted on the thinner side within the depth band,
+/// scaled from 100% at zero spread down to 50% at max_spread_bps. Wider quotes, or a side
+/// without a price, score zero.
+fn quote_score(bid: u64, ask: u64, mid: u64, bid_depth: u64, ask_depth: u64, max_spread_bps: u16) -> Result<u64> {
+    if bid == 0 || ask == 0 {
+        return Ok(0);
+    }
+    require!(bid < ask && bid <= mid && mid <= ask, CustomError::InvalidQuote);
+    let max_spread = max_spread_bps as u128;
+    let spread_bps = (ask - bid) as u128 * BPS_DENOMINATOR as u128 / mid as u128;
+    if spread_bps > max_spread {
+        return Ok(0);
+    }
+    let depth = bid_depth.min(ask_depth) as u128;
+    Ok((depth * (2 * max_spread - spread_bps) / (2 * max_spread)) as u64)
+}
+
+/// Stake history period containing `timestamp`.
+fn stake_checkpoint_period(timestamp: i64) -> i64 {
+    timestamp.div_euclid(STAKE_CHECKPOINT_PERIOD)
+}
+
+/// veLVT voting power: stake weighted by remaining lock time (capped at VE_MAX_LOCK),
+/// decaying linearly to zero at lockup_end.
+fn ve_power(amount: u64, lockup_end: i64, now: i64) -> u64 {
+    let remaining = lockup_end.saturating_sub(now).clamp(0, VE_MAX_LOCK) as u128;
+    (amount as u128 * remaining / VE_MAX_LOCK as u128) as u64
+}
+
+/// veLVT delegated to a user at `now`, from the aggregates Σ amount and Σ amount * lockup_end.
+/// Delegations whose lock expired undercount until refreshed, never overcount.
+fn delegated_ve_power(delegated_amount: u64, delegated_lock_weight: u128, now: i64) -> u64 {
+    let decayed = delegated_amount as u128 * now.max(0) as u128;
+    (delegated_lock_weight.saturating_sub(decayed) / VE_MAX_LOCK as u128) as u64
+}
+
+/// Reward boost from veLVT, Curve-style: VE_MAX_BOOST_BPS extra once the user's veLVT reaches
+/// VE_BOOST_COVERAGE_BPS of their volume this epoch, and proportionally less below that.
+fn ve_boost_bps(user_state: &UserState, now: i64) -> u64 {
+    let required = bps_of(user_state.epoch_volume, VE_BOOST_COVERAGE_BPS).max(1);
+    let ve = user_state.ve_power(now).min(required);
+    BPS_DENOMINATOR + (VE_MAX_BOOST_BPS as u128 * ve as u128 / required as u128) as u64
+}
+
+/// Keep a delegator's Delegation and their delegate's veLVT aggregates in line with their stake,
+/// checkpointing the delegate. All three accounts are required whenever the user has delegated.
+fn sync_delegation(
+    user_state: &UserState,
+    delegation: Option<&mut Delegation>,
+    delegate_state: Option<&mut UserState>,
+    delegate_history: Option<&mut StakeHistory>,
+    now: i64,
+) -> Result<()> {
+    if user_state.delegate == Pubkey::default() {
+        return Ok(());
+    }
+    let (Some(delegation), Some(delegate_state), Some(delegate_history)) =
+        (delegation, delegate_state, delegate_history)
+    else {
+        return err!(CustomError::MissingDelegation);
+    };
+    require!(
+        delegation.delegator == user_state.owner
+            && delegate_state.owner == delegation.delegate
+            && delegate_history.owner == delegation.delegate,
+        CustomError::MissingDelegation
+    );
+    delegate_state.debit_delegation(delegation);
+    delegation.track(user_state, now);
+    delegate_state.credit_delegation(delegation);
+    delegate_history.record(delegate_state, now);
+    Ok(())
+}
+
+/// Copy the benefits of the tier matching the user's current stake onto the user state.
+fn apply_stake_tier(user_state: &mut UserState, tier_config: &TierConfig) {
+    match tier_config.resolve(user_state.staked_amount) {
+        Some(tier) => {
+            user_state.fee_discount = tier.fee_discount_bps as u64;
+            user_state.trading_rebate = tier.rebate_bps as u64;
+            user_state.execution_priority = tier.execution_priority;
+            user_state.tier_boost_bps = tier.boost_multiplier_bps as u64;
+        }
+        None => {
+            user_state.fee_discount = 0;
+            user_state.trading_rebate = 0;
+            user_state.execution_priority = false;
+            user_state.tier_boost_bps = BPS_DENOMINATOR;
+        }
+    }
+}
+
+//
+// ACCOUNT CONTEXTS AND STRUCTS
+//
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = admin, space = 8 + State::LEN, seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        constraint = treasury.mint == lvt_mint.key(),
+        constraint = treasury.owner == treasury_authority.key() @ CustomError::InvalidTreasuryAuthority
+    )]
+    pub treasury: Account<'info, TokenAccount>, // Treasury for LVT tokens.
+    /// CHECK: PDA that owns the treasury and every bucket vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(mut)]
+    pub lvt_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[account]
+pub struct State {
+    pub total_trades: u64,
+    pub total_liquidity: u64,
+    pub fee_rate: u64, // In millionths of notional (see FEE_RATE_DENOMINATOR).
+    pub last_fee_update: i64,
+    pub treasury: Pubkey,
+    // For dynamic reward adjustment:
+    pub reward_sum: u64,
+    pub reward_count: u64,
+    pub global_reward_multiplier: u64,
+    // Additional governance or protocol fields can be added here.
+    pub admin: Pubkey,
+    pub lvt_mint: Pubkey,
+    pub treasury_authority_bump: u8,
+    // Treasury split in basis points: traders & LPs, staking, development, ecosystem.
+    pub treasury_split_bps: [u16; 4],
+    // Claim cooldown and vesting schedule (seconds), plus acceleration for staked vesting balances.
+    pub claim_cooldown: i64,
+    pub vesting_cliff: i64,
+    pub vesting_duration: i64,
+    pub vesting_accel_bps: u16,
+    // Stake withdrawal: unbonding period (seconds) and penalty for breaking a lockup.
+    pub unbonding_period: i64,
+    pub early_exit_penalty_bps: u16,
+    pub compliance_authority: Pubkey, // May manage institutional profiles alongside the admin.
+    // Randomized batch processing.
+    pub shuffle_batch_count: u64,
+    pub vrf_program: Pubkey, // Owner of accepted VRF result accounts; default = none.
+    // Keeper-advanced periods.
+    pub reward_epoch: u64,
+    pub epoch_started_at: i64,
+    pub leaderboard_season: u64,
+    // Governance.
+    pub proposal_count: u64,
+    pub voting_period: i64,
+    pub quorum_votes: u64,
+    pub timelock_delay: i64, // Seconds between a proposal being queued and becoming executable.
+    pub guardian: Pubkey,    // May veto queued proposals.
+    pub paused: bool,        // Set by governance; blocks trading, staking and claims.
+    pub trade_venue: Pubkey, // Signs every fill passed to record_trade.
+    pub bump: u8,            // State is a singleton PDA at [b"state"].
+}
+
+impl State {
+    // Calculation: 3 u64 fields (24 bytes) + last_fee_update (8) + treasury (32) = 64;
+    // plus 3 more u64 fields (24) = 88 bytes.
+    // Treasury: admin (32) + lvt_mint (32) + authority bump (1) + split (4 * 2) = 73 bytes.
+    // Claims: cooldown, cliff, duration (3 * 8) + accel bps (2) = 26 bytes.
+    // Unstaking: unbonding period (8) + penalty bps (2) = 10 bytes.
+    // Compliance authority (32).
+    // Shuffles: batch count (8) + VRF program (32).
+    // Periods: reward epoch (8) + epoch start (8) + leaderboard season (8).
+    // Governance: proposal count (8) + voting period (8) + quorum (8)
+    // + timelock delay (8) + guardian (32) + paused (1).
+    // Trade venue (32) + bump (1).
+    pub const LEN: usize = 88 + 73 + 26 + 10 + 32 + 40 + 24 + 24 + 41 + 32 + 1;
+}
+
+#[derive(Accounts)]
+pub struct RecordTrade<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = trade_venue @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    pub trade_venue: Signer<'info>,
+    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Log detailed trade data.
+    #[account(init, payer = payer, space = 8 + TradeRecord::LEN)]
+    pub trade_record: Account<'info, TradeRecord>,
+    // Required when user_state.is_institutional is set.
+    #[account(mut, seeds = [b"institutional", user_state.owner.as_ref()], bump = institutional_profile.bump)]
+    pub institutional_profile: Option<Account<'info, InstitutionalProfile>>,
+    #[account(mut, seeds = [b"leaderboard", user_state.owner.as_ref()], bump = leaderboard.bump)]
+    pub leaderboard: Account<'info, TraderLeaderboard>,
+    #[account(mut, seeds = [b"leaderboard_top", state.key().as_ref()], bump = leaderboard_top_n.load()?.bump)]
+    pub leaderboard_top_n: AccountLoader<'info, LeaderboardTopN>,
+    /// CHECK: WalletLink PDA of the user and the counterparty, checked in the handler. Usually
+    /// uninitialized, which means the wallets are not linked.
+    pub wallet_link: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// A link between two wallets believed to have one owner; trades between them are wash trades.
+/// Keyed by the two wallets in ascending order.
+#[account]
+pub struct WalletLink {
+    pub wallet_a: Pubkey,
+    pub wallet_b: Pubkey,
+    pub declared: bool, // Declared by one of the wallets.
+    pub flagged: bool,  // Flagged by the admin or compliance authority.
+    pub bump: u8,
+}
+
+impl WalletLink {
+    pub const LEN: usize = 32 + 32 + 1 + 1 + 1;
+}
+
+#[derive(Accounts)]
+#[instruction(wallet_a: Pubkey, wallet_b: Pubkey)]
+pub struct DeclareWalletLink<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + WalletLink::LEN,
+        seeds = [b"wallet_link", wallet_a.as_ref(), wallet_b.as_ref()],
+        bump
+    )]
+    pub wallet_link: Account<'info, WalletLink>,
+    #[account(mut, constraint = owner.key() == wallet_a || owner.key() == wallet_b @ CustomError::Unauthorized)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(wallet_a: Pubkey, wallet_b: Pubkey)]
+pub struct FlagWalletLink<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + WalletLink::LEN,
+        seeds = [b"wallet_link", wallet_a.as_ref(), wallet_b.as_ref()],
+        bump
+    )]
+    pub wallet_link: Account<'info, WalletLink>,
+    #[account(
+        mut,
+        constraint = authority.key() == state.admin
+            || authority.key() == state.compliance_authority @ CustomError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetWalletLinkFlag<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"wallet_link", wallet_link.wallet_a.as_ref(), wallet_link.wallet_b.as_ref()],
+        bump = wallet_link.bump
+    )]
+    pub wallet_link: Account<'info, WalletLink>,
+    #[account(
+        constraint = authority.key() == state.admin
+            || authority.key() == state.compliance_authority @ CustomError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+/// A fill passed to record_trade. The counterparty, side, maker/taker flag and prices come from
+/// the venue's matching and are attested by its signature, not chosen by the trader.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TradeParams {
+    pub trade_amount: u64,
+    pub trade_timestamp: i64,
+    pub trade_pair: String,
+    pub execution_delay: i64,
+    pub slippage: u64,
+    pub liquidity_provided: u64,
+    pub counterparty: Pubkey, // For the wash trading checks.
+    // Fill details used to classify the trader's strategy.
+    pub side: OrderSide,
+    pub liquidity: LiquiditySide,
+    pub price: u64,
+    pub reference_price: u64, // Price of the pair on another market; 0 if unknown.
+}
+
+#[account]
+pub struct TradeRecord {
+    pub user: Pubkey,
+    pub trade_amount: u64,
+    pub trade_timestamp: i64,
+    pub trade_pair: String, // Consider fixed-length or hashed representation in production.
+    pub execution_delay: i64,
+    pub slippage: u64,
+    pub liquidity_provided: u64,
+    // Strategy classification inputs; replaying a user's records per epoch reproduces UserState's stats.
+    pub epoch: u64,
+    pub side: OrderSide,
+    pub liquidity: LiquiditySide,
+    pub price: u64,
+    pub reference_price: u64,
+    pub flagged: bool, // Suspected wash trade: recorded, but earned no reward.
+    pub wash_flags: u8, // WASH_* signals that flagged it.
+}
+
+impl TradeRecord {
+    // For example, trade_pair is limited to 32 bytes.
+    // Classification: epoch (8) + side (1) + liquidity (1) + price (8) + reference_price (8).
+    // Wash detection: flagged (1) + wash_flags (1).
+    pub const LEN: usize = 32 + 8 + 8 + 32 + 8 + 8 + 8 + 26 + 2;
+}
+
+#[derive(Accounts)]
+pub struct RecordLiquidityDeposit<'info> {
     #[account(mut, seeds = [b"lp", lp_state.owner.as_ref()], bump = lp_state.bump)]
     pub lp_state: Account<'info, LPState>,
     pub owner: Signer<'info>,
 }
 
 #[account]
-pub struct LPState {
-    pub owner: Pubkey,
-    pub total_deposit: u64,
-    pub last_deposit: i64,
+pub struct LPState {
+    pub owner: Pubkey,
+    pub total_deposit: u64,
+    pub last_deposit: i64,
+    pub bump: u8,
+}
+
+impl LPState {
+    pub const LEN: usize = 32 + 8 + 8 + 1;
+}
+
+#[derive(Accounts)]
+pub struct InitStakeVault<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized,
+        has_one = lvt_mint
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"stake_vault", state.key().as_ref()],
+        bump,
+        token::mint = lvt_mint,
+        token::authority = treasury_authority
+    )]
+    pub stake_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub lvt_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct StakeTokens<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakePosition::LEN,
+        seeds = [b"stake_position", owner.key().as_ref(), user_state.position_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    #[account(has_one = state, seeds = [b"tier_config", state.key().as_ref()], bump = tier_config.bump)]
+    pub tier_config: Account<'info, TierConfig>,
+    #[account(mut, seeds = [b"staking_pool", state.key().as_ref()], bump = staking_pool.bump)]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(seeds = [b"bucket_vault", state.key().as_ref(), &[BUCKET_STAKING]], bump)]
+    pub staking_vault: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"stake_vault", state.key().as_ref()], bump)]
+    pub stake_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = state.lvt_mint, token::authority = owner)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"delegation", owner.key().as_ref()], bump = delegation.bump)]
+    pub delegation: Option<Account<'info, Delegation>>,
+    #[account(mut, seeds = [b"stake_history", owner.key().as_ref()], bump = stake_history.bump)]
+    pub stake_history: Account<'info, StakeHistory>,
+    #[account(mut, seeds = [b"user", delegate_state.owner.as_ref()], bump = delegate_state.bump)]
+    pub delegate_state: Option<Account<'info, UserState>>,
+    #[account(mut, seeds = [b"stake_history", delegate_history.owner.as_ref()], bump = delegate_history.bump)]
+    pub delegate_history: Option<Account<'info, StakeHistory>>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestUnstake<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"stake_position", owner.key().as_ref(), stake_position.index.to_le_bytes().as_ref()],
+        bump = stake_position.bump,
+        has_one = owner
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    #[account(has_one = state, seeds = [b"tier_config", state.key().as_ref()], bump = tier_config.bump)]
+    pub tier_config: Account<'info, TierConfig>,
+    #[account(mut, seeds = [b"staking_pool", state.key().as_ref()], bump = staking_pool.bump)]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(seeds = [b"bucket_vault", state.key().as_ref(), &[BUCKET_STAKING]], bump)]
+    pub staking_vault: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"delegation", owner.key().as_ref()], bump = delegation.bump)]
+    pub delegation: Option<Account<'info, Delegation>>,
+    #[account(mut, seeds = [b"stake_history", owner.key().as_ref()], bump = stake_history.bump)]
+    pub stake_history: Account<'info, StakeHistory>,
+    #[account(mut, seeds = [b"user", delegate_state.owner.as_ref()], bump = delegate_state.bump)]
+    pub delegate_state: Option<Account<'info, UserState>>,
+    #[account(mut, seeds = [b"stake_history", delegate_history.owner.as_ref()], bump = delegate_history.bump)]
+    pub delegate_history: Option<Account<'info, StakeHistory>>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyUnstake<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"stake_position", owner.key().as_ref(), stake_position.index.to_le_bytes().as_ref()],
+        bump = stake_position.bump,
+        has_one = owner
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    #[account(has_one = state, seeds = [b"tier_config", state.key().as_ref()], bump = tier_config.bump)]
+    pub tier_config: Account<'info, TierConfig>,
+    #[account(mut, seeds = [b"staking_pool", state.key().as_ref()], bump = staking_pool.bump)]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(mut, seeds = [b"stake_vault", state.key().as_ref()], bump)]
+    pub stake_vault: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_STAKING]], bump = staking_bucket.bump)]
+    pub staking_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = staking_bucket.vault)]
+    pub staking_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"delegation", owner.key().as_ref()], bump = delegation.bump)]
+    pub delegation: Option<Account<'info, Delegation>>,
+    #[account(mut, seeds = [b"stake_history", owner.key().as_ref()], bump = stake_history.bump)]
+    pub stake_history: Account<'info, StakeHistory>,
+    #[account(mut, seeds = [b"user", delegate_state.owner.as_ref()], bump = delegate_state.bump)]
+    pub delegate_state: Option<Account<'info, UserState>>,
+    #[account(mut, seeds = [b"stake_history", delegate_history.owner.as_ref()], bump = delegate_history.bump)]
+    pub delegate_history: Option<Account<'info, StakeHistory>>,
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CompleteUnstake<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"stake_position", owner.key().as_ref(), stake_position.index.to_le_bytes().as_ref()],
+        bump = stake_position.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    #[account(mut, seeds = [b"stake_vault", state.key().as_ref()], bump)]
+    pub stake_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = owner)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitStakingPool<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + StakingPool::LEN,
+        seeds = [b"staking_pool", state.key().as_ref()],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SyncStakingPool<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"staking_pool", state.key().as_ref()], bump = staking_pool.bump)]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(seeds = [b"bucket_vault", state.key().as_ref(), &[BUCKET_STAKING]], bump)]
+    pub staking_vault: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct HarvestStakingYield<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"staking_pool", state.key().as_ref()], bump = staking_pool.bump)]
+    pub staking_pool: Account<'info, StakingPool>,
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_STAKING]], bump = staking_bucket.bump)]
+    pub staking_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = staking_bucket.vault)]
+    pub staking_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = owner)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// MasterChef-style pool sharing staking bucket inflows across all stakers by weighted stake.
+#[account]
+pub struct StakingPool {
+    pub state: Pubkey,
+    pub total_weight: u64,         // Sum of every user's weighted_stake.
+    pub acc_reward_per_share: u128, // Scaled by ACC_REWARD_PRECISION.
+    pub reserved: u64,             // Staking vault balance already credited to stakers but not harvested.
+    pub bump: u8,
+}
+
+impl StakingPool {
+    pub const LEN: usize = 32 + 8 + 16 + 8 + 1;
+
+    /// Credit any staking vault balance not yet accounted for to the current stakers.
+    /// Inflows that arrive while nobody is staked wait for the next sync.
+    pub fn sync(&mut self, vault_amount: u64) {
+        let inflow = vault_amount.saturating_sub(self.reserved);
+        if inflow == 0 || self.total_weight == 0 {
+            return;
+        }
+        self.acc_reward_per_share = self
+            .acc_reward_per_share
+            .checked_add(inflow as u128 * ACC_REWARD_PRECISION / self.total_weight as u128)
+            .unwrap();
+        self.reserved = self.reserved.checked_add(inflow).unwrap();
+    }
+
+    fn accumulated(&self, weight: u64) -> u128 {
+        weight as u128 * self.acc_reward_per_share / ACC_REWARD_PRECISION
+    }
+
+    /// Settle the user's pending yield at their current weight, then move them to `new_weight`.
+    pub fn reweight(&mut self, user_state: &mut UserState, new_weight: u64) {
+        let pending = self
+            .accumulated(user_state.weighted_stake)
+            .saturating_sub(user_state.staking_reward_debt);
+        user_state.pending_staking_yield = user_state
+            .pending_staking_yield
+            .checked_add(pending as u64)
+            .unwrap();
+        self.total_weight = self
+            .total_weight
+            .checked_sub(user_state.weighted_stake)
+            .unwrap()
+            .checked_add(new_weight)
+            .unwrap();
+        user_state.weighted_stake = new_weight;
+        user_state.staking_reward_debt = self.accumulated(new_weight);
+    }
+}
+
+#[account]
+pub struct StakePosition {
+    pub owner: Pubkey,
+    pub index: u64,
+    pub amount: u64,
+    pub lockup_start: i64,
+    pub lockup_end: i64,
+    pub yield_multiplier_bps: u64, // Duration-based yield multiplier fixed at deposit.
+    pub bump: u8,
+    pub unbonding_end: i64,        // When the position can be withdrawn; 0 while still staked.
+}
+
+impl StakePosition {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 8 + 1 + 8;
+
+    /// Stake amount weighted by the position's lockup yield multiplier.
+    pub fn weighted_amount(&self) -> u64 {
+        bps_of(self.amount, self.yield_multiplier_bps)
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitTierConfig<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized,
+        has_one = lvt_mint
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + TierConfig::LEN,
+        seeds = [b"tier_config", state.key().as_ref()],
+        bump
+    )]
+    pub tier_config: Account<'info, TierConfig>,
+    pub lvt_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshTier<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(has_one = state, seeds = [b"tier_config", state.key().as_ref()], bump = tier_config.bump)]
+    pub tier_config: Account<'info, TierConfig>,
+}
+
+/// Tier as submitted by governance; the minimum stake is in whole LVT.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct TierParams {
+    pub min_stake_tokens: u64,
+    pub fee_discount_bps: u16,
+    pub rebate_bps: u16,
+    pub execution_priority: bool,
+    pub boost_multiplier_bps: u16,
+}
+
+impl TierParams {
+    pub const LEN: usize = 8 + 2 + 2 + 1 + 2;
+}
+
+/// Tier as stored on-chain; the minimum stake is in base units of the LVT mint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct StakeTier {
+    pub min_stake: u64,
+    pub fee_discount_bps: u16,
+    pub rebate_bps: u16,
+    pub execution_priority: bool,
+    pub boost_multiplier_bps: u16,
+}
+
+impl StakeTier {
+    pub const LEN: usize = 8 + 2 + 2 + 1 + 2;
+}
+
+#[account]
+pub struct TierConfig {
+    pub state: Pubkey,
+    pub decimals: u8,
+    pub tiers: Vec<StakeTier>, // Ordered by ascending min_stake.
+    pub bump: u8,
+}
+
+impl TierConfig {
+    pub const MAX_TIERS: usize = 8;
+    pub const LEN: usize = 32 + 1 + 4 + Self::MAX_TIERS * StakeTier::LEN + 1;
+
+    /// Highest tier whose minimum stake is met, if any.
+    pub fn resolve(&self, staked: u64) -> Option<&StakeTier> {
+        self.tiers.iter().rev().find(|tier| staked >= tier.min_stake)
+    }
+
+    pub fn set_tiers(&mut self, params: &[TierParams]) -> Result<()> {
+        validate_tier_params(params)?;
+        let scale = 10u64.checked_pow(self.decimals as u32).unwrap();
+        let mut tiers: Vec<StakeTier> = Vec::with_capacity(params.len());
+        for p in params {
+            let min_stake = p
+                .min_stake_tokens
+                .checked_mul(scale)
+                .ok_or(CustomError::InvalidTierConfig)?;
+            if let Some(prev) = tiers.last() {
+                require!(min_stake > prev.min_stake, CustomError::InvalidTierConfig);
+            }
+            tiers.push(StakeTier {
+                min_stake,
+                fee_discount_bps: p.fee_discount_bps,
+                rebate_bps: p.rebate_bps,
+                execution_priority: p.execution_priority,
+                boost_multiplier_bps: p.boost_multiplier_bps,
+            });
+        }
+        self.tiers = tiers;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_TRADERS_LPS]], bump = reward_bucket.bump)]
+    pub reward_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = reward_bucket.vault)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = owner)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    // Only required when claiming into vesting.
+    #[account(mut, seeds = [b"vesting", owner.key().as_ref()], bump = vesting_account.bump)]
+    pub vesting_account: Option<Account<'info, VestingAccount>>,
+    #[account(mut, seeds = [b"vesting_vault", owner.key().as_ref()], bump)]
+    pub vesting_vault: Option<Account<'info, TokenAccount>>,
+    /// CHECK: PDA that owns the treasury and every bucket vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct OpenVestingAccount<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = lvt_mint)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + VestingAccount::LEN,
+        seeds = [b"vesting", owner.key().as_ref()],
+        bump
+    )]
+    pub vesting_account: Account<'info, VestingAccount>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"vesting_vault", owner.key().as_ref()],
+        bump,
+        token::mint = lvt_mint,
+        token::authority = treasury_authority
+    )]
+    pub vesting_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub lvt_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawVested<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"vesting", owner.key().as_ref()], bump = vesting_account.bump, has_one = owner)]
+    pub vesting_account: Account<'info, VestingAccount>,
+    #[account(mut, address = vesting_account.vault)]
+    pub vesting_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = owner)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AccelerateVesting<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        seeds = [b"stake_position", owner.key().as_ref(), stake_position.index.to_le_bytes().as_ref()],
+        bump = stake_position.bump,
+        has_one = owner
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    #[account(mut, seeds = [b"vesting", owner.key().as_ref()], bump = vesting_account.bump, has_one = owner)]
+    pub vesting_account: Account<'info, VestingAccount>,
+    pub owner: Signer<'info>,
+}
+
+#[account]
+pub struct VestingAccount {
+    pub owner: Pubkey,
+    pub vault: Pubkey,
+    pub tranches: Vec<VestingTranche>, // One per claim, each on its own schedule.
+    pub bump: u8,
+}
+
+impl VestingAccount {
+    pub const MAX_TRANCHES: usize = 8;
+    pub const LEN: usize = 32 + 32 + (4 + Self::MAX_TRANCHES * VestingTranche::LEN) + 1;
+
+    /// Vest a new claim on its own schedule; earlier tranches keep theirs.
+    pub fn add(&mut self, amount: u64, now: i64, cliff: i64, duration: i64) -> Result<()> {
+        require!(self.tranches.len() < Self::MAX_TRANCHES, CustomError::TooManyVestingTranches);
+        self.tranches.push(VestingTranche {
+            locked_amount: amount,
+            unlocked_amount: 0,
+            withdrawn_amount: 0,
+            start_time: now,
+            cliff_end: now + cliff,
+            end_time: now + duration,
+            accelerated: false,
+        });
+        Ok(())
+    }
+
+    pub fn withdrawable(&self, now: i64) -> u64 {
+        self.tranches.iter().map(|tranche| tranche.withdrawable(now)).sum()
+    }
+
+    /// Amount not released yet.
+    pub fn locked(&self, now: i64) -> u64 {
+        self.tranches.iter().map(|tranche| tranche.locked_amount - tranche.vested(now)).sum()
+    }
+
+    /// Withdraw from every tranche and drop the ones fully paid out.
+    pub fn withdraw(&mut self, now: i64) -> u64 {
+        let amount = self.tranches.iter_mut().map(|tranche| tranche.withdraw(now)).sum();
+        self.tranches.retain(|tranche| tranche.withdrawn_amount < tranche.locked_amount);
+        amount
+    }
+
+    /// Shorten the remaining schedule of each tranche not accelerated yet to `accel_bps` of it.
+    /// Returns the amount still locked in those tranches and the latest new end time.
+    pub fn accelerate(&mut self, now: i64, accel_bps: u16) -> (u64, i64) {
+        let mut locked = 0u64;
+        let mut latest_end = now;
+        for tranche in self.tranches.iter_mut().filter(|tranche| !tranche.accelerated) {
+            tranche.rebase(now);
+            if tranche.locked_amount == 0 {
+                continue;
+            }
+            let remaining = tranche.end_time.saturating_sub(now);
+            let new_end = now.checked_add(remaining * accel_bps as i64 / BPS_DENOMINATOR as i64).unwrap();
+            tranche.end_time = new_end;
+            tranche.cliff_end = tranche.cliff_end.min(new_end);
+            tranche.accelerated = true;
+            locked = locked.checked_add(tranche.locked_amount).unwrap();
+            latest_end = latest_end.max(new_end);
+        }
+        (locked, latest_end)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct VestingTranche {
+    pub locked_amount: u64,    // Amount released linearly between start_time and end_time.
+    pub unlocked_amount: u64,  // Already released by a previous schedule, not yet withdrawn.
+    pub withdrawn_amount: u64, // Withdrawn out of locked_amount.
+    pub start_time: i64,
+    pub cliff_end: i64,
+    pub end_time: i64,
+    pub accelerated: bool,
+}
+
+impl VestingTranche {
+    pub const LEN: usize = 8 + 8 + 8 + 8 + 8 + 8 + 1;
+
+    /// Portion of locked_amount released by `now` (nothing before the cliff, linear afterwards).
+    pub fn vested(&self, now: i64) -> u64 {
+        if now < self.cliff_end {
+            0
+        } else if now >= self.end_time {
+            self.locked_amount
+        } else {
+            let elapsed = (now - self.start_time) as u128;
+            let duration = (self.end_time - self.start_time) as u128;
+            ((self.locked_amount as u128) * elapsed / duration) as u64
+        }
+    }
+
+    pub fn withdrawable(&self, now: i64) -> u64 {
+        self.unlocked_amount + self.vested(now) - self.withdrawn_amount
+    }
+
+    /// Restart the schedule at `now`, moving everything already released into unlocked_amount.
+    pub fn rebase(&mut self, now: i64) {
+        let vested = self.vested(now);
+        self.unlocked_amount = self.withdrawable(now);
+        self.locked_amount -= vested;
+        self.withdrawn_amount = 0;
+        self.start_time = now;
+    }
+
+    /// Mark everything released so far as withdrawn and return the amount.
+    pub fn withdraw(&mut self, now: i64) -> u64 {
+        let amount = self.withdrawable(now);
+        self.withdrawn_amount = self.vested(now);
+        self.unlocked_amount = 0;
+        amount
+    }
+}
+
+#[derive(Accounts)]
+pub struct StateCrank<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        constraint = keeper.keeper_registry.state == state.key() @ CustomError::InvalidKeeperRegistry
+    )]
+    pub state: Account<'info, State>,
+    pub keeper: KeeperBounty<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AutoAdjustFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct CreateMarket<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Market::LEN,
+        seeds = [b"market", state.key().as_ref(), pair.as_bytes()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarket<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteFee<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    #[account(seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(seeds = [b"institutional", user_state.owner.as_ref()], bump = institutional_profile.bump)]
+    pub institutional_profile: Option<Account<'info, InstitutionalProfile>>,
+}
+
+#[derive(Accounts)]
+pub struct SetComplianceAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenInstitutionalProfile<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + InstitutionalProfile::LEN,
+        seeds = [b"institutional", user_state.owner.as_ref()],
+        bump
+    )]
+    pub institutional_profile: Account<'info, InstitutionalProfile>,
+    #[account(
+        mut,
+        constraint = authority.key() == state.admin
+            || authority.key() == state.compliance_authority @ CustomError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateInstitutionalProfile<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"institutional", user_state.owner.as_ref()], bump = institutional_profile.bump)]
+    pub institutional_profile: Account<'info, InstitutionalProfile>,
+    #[account(
+        constraint = authority.key() == state.admin
+            || authority.key() == state.compliance_authority @ CustomError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeInstitutionalProfile<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"institutional", user_state.owner.as_ref()],
+        bump = institutional_profile.bump,
+        close = authority
+    )]
+    pub institutional_profile: Account<'info, InstitutionalProfile>,
+    #[account(
+        mut,
+        constraint = authority.key() == state.admin
+            || authority.key() == state.compliance_authority @ CustomError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+/// Negotiated terms for a whitelisted institutional trader.
+#[account]
+pub struct InstitutionalProfile {
+    pub owner: Pubkey,
+    pub fee_rate: u64,          // Negotiated fee rate in millionths; 0 = market institutional schedule.
+    pub volume_commitment: u64, // Volume the institution committed to trade before expires_at.
+    pub volume_to_date: u64,    // Volume recorded since starts_at.
+    pub starts_at: i64,
+    pub expires_at: i64,
+    pub approved_by: Pubkey,    // Admin or compliance signer that last approved the terms.
+    pub bump: u8,
+}
+
+impl InstitutionalProfile {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 8 + 32 + 1;
+
+    pub fn is_active(&self, now: i64) -> bool {
+        now < self.expires_at
+    }
+}
+
+#[account]
+pub struct Market {
+    pub state: Pubkey,
+    pub pair: String,                // e.g. "LVT/USDC", at most MAX_PAIR_LEN bytes.
+    pub fee_rate: u64,               // 0 = follow State.fee_rate.
+    pub institutional_fee_rate: u64, // Standard institutional schedule; 0 = none.
+    pub promo_discount_bps: u16,
+    pub promo_start: i64,
+    pub promo_end: i64,
+    pub bump: u8,
+    pub auction_count: u64,          // Next batch auction id.
+    pub commit_window: i64,          // Batch auction commit phase length (seconds).
+    pub reveal_window: i64,          // Batch auction reveal phase length (seconds).
+}
+
+impl Market {
+    pub const MAX_PAIR_LEN: usize = 32;
+    pub const LEN: usize = 32 + (4 + Self::MAX_PAIR_LEN) + 8 + 8 + 2 + 8 + 8 + 1 + 8 + 8 + 8;
+
+    pub fn promotion_active(&self, now: i64) -> bool {
+        self.promo_discount_bps > 0 && now >= self.promo_start && now < self.promo_end
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LiquiditySide {
+    Maker,
+    Taker,
+}
+
+/// Returned by `quote_fee`. All amounts are in units of the trade notional.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeQuote {
+    pub fee_rate: u64,
+    pub base_fee: u64,
+    pub discount: u64,
+    pub rebate: u64,
+    pub effective_fee: u64,
+}
+
+#[derive(Accounts)]
+pub struct InitOrderQueue<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + OrderQueue::LEN,
+        seeds = [b"order_queue", market.key().as_ref()],
+        bump
+    )]
+    pub order_queue: AccountLoader<'info, OrderQueue>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOrderQueue<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market)]
+    pub order_queue: AccountLoader<'info, OrderQueue>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EnqueueOrder<'info> {
+    #[account(seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut)]
+    pub order_queue: AccountLoader<'info, OrderQueue>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut)]
+    pub order_queue: AccountLoader<'info, OrderQueue>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CrankBatch<'info> {
+    #[account(constraint = keeper.keeper_registry.state == market.state @ CustomError::InvalidKeeperRegistry)]
+    pub market: Account<'info, Market>,
+    #[account(mut, has_one = market)]
+    pub order_queue: AccountLoader<'info, OrderQueue>,
+    pub keeper: KeeperBounty<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenBatchAuction<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = lvt_mint)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + BatchAuction::LEN,
+        seeds = [b"auction", market.key().as_ref(), market.auction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub batch_auction: Account<'info, BatchAuction>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"auction_bond", batch_auction.key().as_ref()],
+        bump,
+        token::mint = lvt_mint,
+        token::authority = treasury_authority
+    )]
+    pub bond_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub lvt_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CommitOrder<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", batch_auction.market.as_ref(), batch_auction.batch_id.to_le_bytes().as_ref()],
+        bump = batch_auction.bump
+    )]
+    pub batch_auction: Account<'info, BatchAuction>,
+    #[account(seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + OrderCommitment::LEN,
+        seeds = [b"commitment", batch_auction.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub order_commitment: Account<'info, OrderCommitment>,
+    #[account(mut, address = batch_auction.bond_vault)]
+    pub bond_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = owner)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RevealOrder<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", batch_auction.market.as_ref(), batch_auction.batch_id.to_le_bytes().as_ref()],
+        bump = batch_auction.bump
+    )]
+    pub batch_auction: Account<'info, BatchAuction>,
+    #[account(
+        mut,
+        seeds = [b"commitment", batch_auction.key().as_ref(), owner.key().as_ref()],
+        bump = order_commitment.bump,
+        has_one = owner
+    )]
+    pub order_commitment: Account<'info, OrderCommitment>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBatchAuction<'info> {
+    #[account(
+        mut,
+        seeds = [b"auction", batch_auction.market.as_ref(), batch_auction.batch_id.to_le_bytes().as_ref()],
+        bump = batch_auction.bump
+    )]
+    pub batch_auction: Account<'info, BatchAuction>,
+}
+
+#[derive(Accounts)]
+pub struct CloseOrderCommitment<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()],
+        bump = market.bump,
+        address = batch_auction.market
+    )]
+    pub market: Account<'info, Market>,
+    #[account(
+        seeds = [b"auction", batch_auction.market.as_ref(), batch_auction.batch_id.to_le_bytes().as_ref()],
+        bump = batch_auction.bump
+    )]
+    pub batch_auction: Account<'info, BatchAuction>,
+    #[account(
+        mut,
+        seeds = [b"commitment", batch_auction.key().as_ref(), owner.key().as_ref()],
+        bump = order_commitment.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub order_commitment: Account<'info, OrderCommitment>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    /// CHECK: Receives the rent; checked against order_commitment.owner.
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+    #[account(mut, address = batch_auction.bond_vault)]
+    pub bond_vault: Account<'info, TokenAccount>,
+    // Receives the bond of a revealed commitment.
+    #[account(mut, token::mint = state.lvt_mint, token::authority = owner)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    // Receives the bond of a commitment that was never revealed.
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_STAKING]], bump = staking_bucket.bump)]
+    pub staking_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = staking_bucket.vault)]
+    pub staking_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub closer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AuctionOrder {
+    pub order: QueuedOrder,
+    pub filled: u64, // Size filled at the clearing price once settled.
+}
+
+impl AuctionOrder {
+    pub const LEN: usize = QueuedOrder::LEN + 8;
+}
+
+/// One commit-reveal batch auction on a market.
+#[account]
+pub struct BatchAuction {
+    pub market: Pubkey,
+    pub batch_id: u64,
+    pub commit_end: i64,
+    pub reveal_end: i64,
+    pub commitment_count: u64,
+    pub orders: Vec<AuctionOrder>, // Revealed orders, in reveal order.
+    pub settled: bool,
+    pub clearing_price: u64,
+    pub cleared_volume: u64,
+    pub bump: u8,
+    pub bond_vault: Pubkey, // Escrows commitment bonds until the commitments are closed.
+}
+
+impl BatchAuction {
+    pub const MAX_ORDERS: usize = 32; // Also caps commitments, so every one can be revealed.
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 4 + Self::MAX_ORDERS * AuctionOrder::LEN + 1 + 8 + 8 + 1 + 32;
+}
+
+#[account]
+pub struct OrderCommitment {
+    pub auction: Pubkey,
+    pub owner: Pubkey,
+    pub commitment: [u8; 32],
+    pub committed_at: i64,
+    pub priority: bool,   // Owner's tier execution priority at commit time.
+    pub stake_score: u64, // Owner's weighted stake at commit time.
+    pub sequence: u64,    // Commit order within the auction; final tie-break when filling.
+    pub revealed: bool,
+    pub bump: u8,
+    pub bond: u64,        // LVT escrowed in the auction's bond vault.
+}
+
+impl OrderCommitment {
+    pub const LEN: usize = 32 + 32 + 32 + 8 + 1 + 8 + 8 + 1 + 1 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OrderSide {
+    Bid,
+    Ask,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct QueuedOrder {
+    pub id: u64,
+    pub owner: Pubkey,
+    pub side: OrderSide,
+    pub price: u64,
+    pub size: u64,
+    pub enqueued_at: i64,
+    pub priority: bool,   // Owner's tier had execution priority when the order was queued.
+    pub stake_score: u64, // Owner's weighted stake when the order was queued.
+}
+
+impl QueuedOrder {
+    pub const LEN: usize = 8 + 32 + 1 + 8 + 8 + 8 + 1 + 8;
+}
+
+/// Fixed-size slot in the zero-copy order queue; free while in_use == 0.
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(Default)]
+pub struct QueueSlot {
+    pub owner: Pubkey,
+    pub id: u64,
+    pub price: u64,
+    pub size: u64,
+    pub enqueued_at: i64,
+    pub stake_score: u64,
+    pub side: u8,     // OrderSide as u8.
+    pub priority: u8, // Owner's tier had execution priority when the order was queued.
+    pub in_use: u8,
+    pub _padding: [u8; 5],
+}
+
+impl QueueSlot {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 8 + 1 + 1 + 1 + 5;
+
+    pub fn to_order(&self) -> QueuedOrder {
+        QueuedOrder {
+            id: self.id,
+            owner: self.owner,
+            side: if self.side == OrderSide::Bid as u8 { OrderSide::Bid } else { OrderSide::Ask },
+            price: self.price,
+            size: self.size,
+            enqueued_at: self.enqueued_at,
+            priority: self.priority == 1,
+            stake_score: self.stake_score,
+        }
+    }
+}
+
+#[account(zero_copy(unsafe))]
+#[repr(C)]
+pub struct OrderQueue {
+    pub market: Pubkey,
+    pub next_order_id: u64,
+    pub last_batch_id: u64,
+    pub last_batch_hash: [u8; 32], // Hash chain over every sequenced batch.
+    pub batch_delay: i64,          // Seconds an order waits before it can be cranked.
+    pub bump: u8,
+    pub _padding: [u8; 7],
+    pub orders: [QueueSlot; 64],
+}
+
+impl OrderQueue {
+    pub const MAX_ORDERS: usize = 64;
+    pub const MAX_BATCH: usize = 16;
+    // One wallet cannot hold the whole queue and shut everyone else out.
+    pub const MAX_ORDERS_PER_OWNER: usize = 4;
+    pub const LEN: usize = 32 + 8 + 8 + 32 + 8 + 1 + 7 + Self::MAX_ORDERS * QueueSlot::LEN;
+
+    /// Orders of `owner` waiting in the queue.
+    pub fn queued_by(&self, owner: &Pubkey) -> usize {
+        self.orders
+            .iter()
+            .filter(|slot| slot.in_use == 1 && slot.owner == *owner)
+            .count()
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(mut, seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Proposal::LEN,
+        seeds = [b"proposal", state.key().as_ref(), state.proposal_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(voter: Pubkey)]
+pub struct OpenVoteRecord<'info> {
+    #[account(seeds = [b"proposal", proposal.state.as_ref(), proposal.id.to_le_bytes().as_ref()], bump = proposal.bump)]
+    pub proposal: Account<'info, Proposal>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + VoteRecord::LEN,
+        seeds = [b"vote", proposal.key().as_ref(), voter.as_ref()],
+        bump
+    )]
+    pub vote_record: Account<'info, VoteRecord>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CastVote<'info> {
+    #[account(
+        mut,
+        seeds = [b"proposal", proposal.state.as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    #[account(mut, seeds = [b"vote", proposal.key().as_ref(), owner.key().as_ref()], bump = vote_record.bump)]
+    pub vote_record: Account<'info, VoteRecord>,
+    #[account(seeds = [b"stake_history", owner.key().as_ref()], bump = stake_history.bump)]
+    pub stake_history: Account<'info, StakeHistory>,
+    // Only when the voter had delegated at the snapshot: that delegate's ballot.
+    #[account(
+        mut,
+        seeds = [b"vote", proposal.key().as_ref(), delegate_vote_record.voter.as_ref()],
+        bump = delegate_vote_record.bump
+    )]
+    pub delegate_vote_record: Option<Account<'info, VoteRecord>>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DelegateVotes<'info> {
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Delegation::LEN,
+        seeds = [b"delegation", owner.key().as_ref()],
+        bump
+    )]
+    pub delegation: Account<'info, Delegation>,
+    #[account(mut, seeds = [b"user", delegate_state.owner.as_ref()], bump = delegate_state.bump)]
+    pub delegate_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"stake_history", owner.key().as_ref()], bump = stake_history.bump)]
+    pub stake_history: Account<'info, StakeHistory>,
+    #[account(mut, seeds = [b"stake_history", delegate_state.owner.as_ref()], bump = delegate_history.bump)]
+    pub delegate_history: Account<'info, StakeHistory>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Redelegate<'info> {
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"delegation", owner.key().as_ref()], bump = delegation.bump)]
+    pub delegation: Account<'info, Delegation>,
+    #[account(
+        mut,
+        seeds = [b"user", delegation.delegate.as_ref()],
+        bump = old_delegate_state.bump
+    )]
+    pub old_delegate_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"user", new_delegate_state.owner.as_ref()], bump = new_delegate_state.bump)]
+    pub new_delegate_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"stake_history", owner.key().as_ref()], bump = stake_history.bump)]
+    pub stake_history: Account<'info, StakeHistory>,
+    #[account(mut, seeds = [b"stake_history", delegation.delegate.as_ref()], bump = old_delegate_history.bump)]
+    pub old_delegate_history: Account<'info, StakeHistory>,
+    #[account(mut, seeds = [b"stake_history", new_delegate_state.owner.as_ref()], bump = new_delegate_history.bump)]
+    pub new_delegate_history: Account<'info, StakeHistory>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeDelegation<'info> {
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut, close = owner, seeds = [b"delegation", owner.key().as_ref()], bump = delegation.bump)]
+    pub delegation: Account<'info, Delegation>,
+    #[account(mut, seeds = [b"user", delegation.delegate.as_ref()], bump = delegate_state.bump)]
+    pub delegate_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"stake_history", owner.key().as_ref()], bump = stake_history.bump)]
+    pub stake_history: Account<'info, StakeHistory>,
+    #[account(mut, seeds = [b"stake_history", delegation.delegate.as_ref()], bump = delegate_history.bump)]
+    pub delegate_history: Account<'info, StakeHistory>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshDelegation<'info> {
+    #[account(seeds = [b"user", delegation.delegator.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"delegation", delegation.delegator.as_ref()], bump = delegation.bump)]
+    pub delegation: Account<'info, Delegation>,
+    #[account(mut, seeds = [b"user", delegation.delegate.as_ref()], bump = delegate_state.bump)]
+    pub delegate_state: Account<'info, UserState>,
+    #[account(mut, seeds = [b"stake_history", delegation.delegate.as_ref()], bump = delegate_history.bump)]
+    pub delegate_history: Account<'info, StakeHistory>,
+}
+
+#[derive(Accounts)]
+pub struct InitStakeHistory<'info> {
+    #[account(seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakeHistory::LEN,
+        seeds = [b"stake_history", owner.key().as_ref()],
+        bump
+    )]
+    pub stake_history: Account<'info, StakeHistory>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// A user's stake and delegation balances at the end of the checkpoint period of `timestamp`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct StakeCheckpoint {
+    pub timestamp: i64, // Unix time of the last change recorded in this checkpoint.
+    pub staked_amount: u64,
+    pub locked_amount: u64,
+    pub lockup_end: i64,
+    pub delegate: Pubkey,
+    pub delegated_amount: u64,
+    pub delegated_lock_weight: u128,
+}
+
+impl StakeCheckpoint {
+    pub const LEN: usize = 8 + 8 + 8 + 8 + 32 + 8 + 16;
+
+    /// Whether both checkpoints hold the same balances, whatever their slots.
+    pub fn same_balances(&self, other: &StakeCheckpoint) -> bool {
+        self.staked_amount == other.staked_amount
+            && self.locked_amount == other.locked_amount
+            && self.lockup_end == other.lockup_end
+            && self.delegate == other.delegate
+            && self.delegated_amount == other.delegated_amount
+            && self.delegated_lock_weight == other.delegated_lock_weight
+    }
+
+    pub fn ve_power(&self, now: i64) -> u64 {
+        ve_power(self.locked_amount, self.lockup_end, now)
+    }
+
+    pub fn delegated_ve_power(&self, now: i64) -> u64 {
+        delegated_ve_power(self.delegated_amount, self.delegated_lock_weight, now)
+    }
+}
+
+/// Checkpoints of a user's stake, one per STAKE_CHECKPOINT_PERIOD with a change, so voting power
+/// can be read as of the end of a past period. Only the most recent MAX_CHECKPOINTS are kept.
+#[account]
+pub struct StakeHistory {
+    pub owner: Pubkey,
+    pub truncated: bool, // Older checkpoints have been dropped.
+    pub checkpoints: Vec<StakeCheckpoint>,
+    pub bump: u8,
+}
+
+impl StakeHistory {
+    pub const MAX_CHECKPOINTS: usize = 32;
+    pub const LEN: usize = 32 + 1 + 4 + Self::MAX_CHECKPOINTS * StakeCheckpoint::LEN + 1;
+
+    /// Record the user's current balances. Changes within one period share a checkpoint, so
+    /// recording never fails however often other users' delegations touch this history.
+    pub fn record(&mut self, user_state: &UserState, now: i64) {
+        let checkpoint = StakeCheckpoint {
+            timestamp: now,
+            staked_amount: user_state.staked_amount,
+            locked_amount: user_state.locked_amount,
+            lockup_end: user_state.lockup_end,
+            delegate: user_state.delegate,
+            delegated_amount: user_state.delegated_amount,
+            delegated_lock_weight: user_state.delegated_lock_weight,
+        };
+        let period = stake_checkpoint_period(now);
+        match self.checkpoints.last_mut() {
+            Some(last) if last.same_balances(&checkpoint) => {}
+            Some(last) if stake_checkpoint_period(last.timestamp) == period => *last = checkpoint,
+            _ => {
+                if self.checkpoints.len() == Self::MAX_CHECKPOINTS {
+                    // With one checkpoint per period, the second one is MAX_CHECKPOINTS - 1
+                    // periods old, well before the snapshot of any proposal still open
+                    // (MAX_VOTING_PERIOD plus one period), so none of them reads the first.
+                    self.checkpoints.remove(0);
+                    self.truncated = true;
+                }
+                self.checkpoints.push(checkpoint);
+            }
+        }
+    }
+
+    /// Balances in effect at the end of `period`; empty before the first checkpoint.
+    pub fn at(&self, period: i64) -> Result<StakeCheckpoint> {
+        let index = self
+            .checkpoints
+            .partition_point(|checkpoint| stake_checkpoint_period(checkpoint.timestamp) <= period);
+        if index == 0 {
+            require!(!self.truncated, CustomError::StakeHistoryUnavailable);
+            return Ok(StakeCheckpoint::default());
+        }
+        Ok(self.checkpoints[index - 1])
+    }
+}
+
+/// A delegator's veLVT assigned to one delegate. `amount` and `lockup_end` are what the delegate
+/// is credited with; both are zero once the delegator's lock has expired.
+#[account]
+pub struct Delegation {
+    pub delegator: Pubkey,
+    pub delegate: Pubkey,
+    pub amount: u64,
+    pub lockup_end: i64,
+    pub bump: u8,
+}
+
+impl Delegation {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 1;
+
+    /// Mirror the delegator's locked stake and lock.
+    pub fn track(&mut self, user_state: &UserState, now: i64) {
+        if user_state.lockup_end > now {
+            self.amount = user_state.locked_amount;
+            self.lockup_end = user_state.lockup_end;
+        } else {
+            self.amount = 0;
+            self.lockup_end = 0;
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct QueueProposal<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"proposal", state.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+}
+
+#[derive(Accounts)]
+pub struct VetoProposal<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = guardian @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"proposal", state.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    pub guardian: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    #[account(mut, seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"proposal", state.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    #[account(mut, has_one = state, seeds = [b"tier_config", state.key().as_ref()], bump = tier_config.bump)]
+    pub tier_config: Option<Account<'info, TierConfig>>,
+    #[account(
+        mut,
+        seeds = [b"bucket", state.key().as_ref(), &[treasury_bucket.bucket]],
+        bump = treasury_bucket.bump
+    )]
+    pub treasury_bucket: Option<Account<'info, TreasuryBucket>>,
+    #[account(mut)]
+    pub bucket_vault: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub recipient: Option<Account<'info, TokenAccount>>,
+    /// CHECK: PDA that owns the treasury and every bucket vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: Option<UncheckedAccount<'info>>,
+    pub token_program: Option<Program<'info, Token>>,
+    #[account(mut, seeds = [b"mm_program", mm_program.market.as_ref()], bump = mm_program.bump)]
+    pub mm_program: Option<Account<'info, MmProgram>>,
+}
+
+/// What a proposal does if it passes.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum ProposalAction {
+    SetFeeRate { fee_rate: u64 },
+    SetTiers { tiers: Vec<TierParams> },
+    TreasurySpend { bucket: u8, recipient: Pubkey, amount: u64 }, // recipient is a token account
+    SetPaused { paused: bool },
+    SetTreasurySplit { split_bps: [u16; 4] },
+    SetClaimConfig {
+        claim_cooldown: i64,
+        vesting_cliff: i64,
+        vesting_duration: i64,
+        vesting_accel_bps: u16,
+    },
+    SetUnstakeConfig { unbonding_period: i64, early_exit_penalty_bps: u16 },
+    SetGovernanceConfig { voting_period: i64, quorum_votes: u64, timelock_delay: i64 },
+    SetGuardian { guardian: Pubkey },
+    SetMmBudget { market: Pubkey, epoch_budget: u64 },
+}
+
+impl ProposalAction {
+    // Tag (1) + largest variant, SetTiers (4 + MAX_TIERS * TierParams::LEN).
+    pub const LEN: usize = 1 + 4 + TierConfig::MAX_TIERS * TierParams::LEN;
+
+    /// Reject actions that could never execute, before anyone votes on them.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            ProposalAction::SetFeeRate { fee_rate } => {
+                require!(*fee_rate >= 500 && *fee_rate <= 5000, CustomError::InvalidFeeRate);
+            }
+            ProposalAction::SetTiers { tiers } => validate_tier_params(tiers)?,
+            ProposalAction::TreasurySpend { bucket, amount, .. } => {
+                require!((*bucket as usize) < TREASURY_BUCKET_COUNT, CustomError::InvalidTreasuryBucket);
+                require!(*amount > 0, CustomError::InvalidProposal);
+            }
+            ProposalAction::SetPaused { .. } | ProposalAction::SetGuardian { .. } | ProposalAction::SetMmBudget { .. } => {}
+            ProposalAction::SetTreasurySplit { split_bps } => {
+                let total: u64 = split_bps.iter().map(|bps| *bps as u64).sum();
+                require!(total == BPS_DENOMINATOR, CustomError::InvalidTreasurySplit);
+            }
+            ProposalAction::SetClaimConfig {
+                claim_cooldown,
+                vesting_cliff,
+                vesting_duration,
+                vesting_accel_bps,
+            } => {
+                require!(
+                    *claim_cooldown >= 0
+                        && *vesting_cliff >= 0
+                        && *vesting_duration > 0
+                        && vesting_cliff <= vesting_duration
+                        && *vesting_accel_bps as u64 <= BPS_DENOMINATOR,
+                    CustomError::InvalidVestingConfig
+                );
+            }
+            ProposalAction::SetUnstakeConfig {
+                unbonding_period,
+                early_exit_penalty_bps,
+            } => {
+                require!(
+                    *unbonding_period >= 0 && *early_exit_penalty_bps as u64 <= BPS_DENOMINATOR,
+                    CustomError::InvalidUnstakeConfig
+                );
+            }
+            ProposalAction::SetGovernanceConfig {
+                voting_period,
+                timelock_delay,
+                ..
+            } => {
+                require!(
+                    *voting_period > 0 && *voting_period <= MAX_VOTING_PERIOD && *timelock_delay >= 0,
+                    CustomError::InvalidDelay
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Proposal {
+    pub state: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: ProposalAction,
+    pub voting_starts: i64,
+    pub voting_ends: i64,
+    pub for_votes: u64,
+    pub against_votes: u64,
+    pub executed: bool,
     pub bump: u8,
+    pub eta: i64,     // Earliest execution time once queued; 0 = not queued.
+    pub vetoed: bool,
+    pub snapshot_period: i64, // Votes use stake checkpoints as of the end of this period.
 }
 
-impl LPState {
-    pub const LEN: usize = 32 + 8 + 8 + 1;
+impl Proposal {
+    pub const LEN: usize = 32 + 8 + 32 + ProposalAction::LEN + 8 + 8 + 8 + 8 + 1 + 1 + 8 + 1 + 8;
+
+    /// Take `power` back from a delegate when its delegator votes directly,
+    /// un-counting it from the delegate's tally if the delegate already voted.
+    pub fn override_delegate(&mut self, delegate_record: &mut VoteRecord, power: u64) {
+        delegate_record.overridden_power = delegate_record.overridden_power.checked_add(power).unwrap();
+        if delegate_record.voted {
+            let taken = power.min(delegate_record.weight);
+            delegate_record.weight -= taken;
+            if delegate_record.support {
+                self.for_votes -= taken;
+            } else {
+                self.against_votes -= taken;
+            }
+        }
+    }
+
+    /// Count `power`, less any power delegators already took back, for one side.
+    pub fn count_vote(&mut self, vote_record: &mut VoteRecord, support: bool, power: u64) -> Result<()> {
+        let weight = power.saturating_sub(vote_record.overridden_power);
+        require!(weight > 0, CustomError::NoVotingPower);
+        if support {
+            self.for_votes = self.for_votes.checked_add(weight).unwrap();
+        } else {
+            self.against_votes = self.against_votes.checked_add(weight).unwrap();
+        }
+        vote_record.support = support;
+        vote_record.weight = weight;
+        vote_record.voted = true;
+        Ok(())
+    }
+
+    /// Quorum is met by all votes cast; a strict majority of them must be for.
+    pub fn passed(&self, quorum_votes: u64) -> bool {
+        let total = self.for_votes.saturating_add(self.against_votes);
+        total >= quorum_votes && self.for_votes > self.against_votes
+    }
+}
+
+/// One voter's ballot on one proposal.
+#[account]
+pub struct VoteRecord {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,
+    pub support: bool,
+    pub weight: u64, // Weight currently counted in the proposal tally.
+    pub bump: u8,
+    pub voted: bool,
+    pub overridden_power: u64, // Delegated power taken back by delegators who voted directly.
+}
+
+impl VoteRecord {
+    pub const LEN: usize = 32 + 32 + 1 + 8 + 1 + 1 + 8;
 }
 
 #[derive(Accounts)]
-pub struct StakeTokens<'info> {
+pub struct UpdateDynamicReward<'info> {
+    #[account(mut, seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct InitLeaderboard<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + TraderLeaderboard::LEN,
+        seeds = [b"leaderboard", user.key().as_ref()],
+        bump
+    )]
+    pub leaderboard: Account<'info, TraderLeaderboard>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitLeaderboardTopN<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + LeaderboardTopN::LEN,
+        seeds = [b"leaderboard_top", state.key().as_ref()],
+        bump
+    )]
+    pub leaderboard_top_n: AccountLoader<'info, LeaderboardTopN>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// A trader's activity over the rolling 24 hours, in hourly buckets. Maintained by record_trade.
+#[account]
+pub struct TraderLeaderboard {
+    pub user: Pubkey,
+    pub trade_volume: u64, // Rolling 24h volume.
+    pub trade_count: u64,  // Rolling 24h trade count.
+    pub last_update: i64,
+    pub bump: u8,
+    pub season: u64, // State.leaderboard_season the counters belong to.
+    pub current_hour: i64, // Hour (unix time / 3600) of the newest bucket.
+    pub hourly_volume: [u64; 24],
+    pub hourly_trades: [u32; 24],
+    pub velocity_score: u64, // Rolling volume scaled by the share of the 24 hours with trades.
+}
+
+impl TraderLeaderboard {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 1 + 8 + 8 + 24 * 8 + 24 * 4 + 8;
+
+    pub fn reset(&mut self, season: u64) {
+        self.season = season;
+        self.hourly_volume = [0; 24];
+        self.hourly_trades = [0; 24];
+        self.trade_volume = 0;
+        self.trade_count = 0;
+        self.velocity_score = 0;
+    }
+
+    /// Clear the buckets of hours that have left the window ending at `hour`.
+    pub fn roll(&mut self, hour: i64) {
+        if hour <= self.current_hour {
+            return;
+        }
+        let expired = (hour - self.current_hour).min(LEADERBOARD_HOURS as i64);
+        for h in (hour - expired + 1)..=hour {
+            let bucket = h.rem_euclid(LEADERBOARD_HOURS as i64) as usize;
+            self.hourly_volume[bucket] = 0;
+            self.hourly_trades[bucket] = 0;
+        }
+        self.current_hour = hour;
+    }
+
+    pub fn record(&mut self, amount: u64, now: i64) {
+        let hour = now / 3600;
+        self.roll(hour);
+        let bucket = hour.rem_euclid(LEADERBOARD_HOURS as i64) as usize;
+        self.hourly_volume[bucket] = self.hourly_volume[bucket].checked_add(amount).unwrap();
+        self.hourly_trades[bucket] = self.hourly_trades[bucket].saturating_add(1);
+        self.trade_volume = self.hourly_volume.iter().fold(0u64, |sum, v| sum.saturating_add(*v));
+        self.trade_count = self.hourly_trades.iter().map(|t| *t as u64).sum();
+        let active_hours = self.hourly_trades.iter().filter(|t| **t > 0).count() as u128;
+        self.velocity_score = (self.trade_volume as u128 * active_hours / LEADERBOARD_HOURS as u128) as u64;
+        self.last_update = now;
+    }
+}
+
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(Default)]
+pub struct RankEntry {
+    pub trader: Pubkey,
+    pub value: u64,
+    pub hour: i64, // Hour the value was recorded; it drops out 24 hours later.
+}
+
+/// Global top-N traders by rolling 24h volume and by velocity score, sorted descending.
+/// Each entry holds the trader's stats as of their last trade.
+#[account(zero_copy(unsafe))]
+#[repr(C)]
+pub struct LeaderboardTopN {
+    pub state: Pubkey,
+    pub season: u64,
+    pub by_volume: [RankEntry; 16],
+    pub by_velocity: [RankEntry; 16],
+    pub prize_curve_bps: [u16; 16], // Kept across seasons.
+    pub bump: u8,
+    pub _padding: [u8; 7],
+}
+
+impl LeaderboardTopN {
+    pub const SIZE: usize = 16;
+    pub const LEN: usize = 32 + 8 + 2 * Self::SIZE * (32 + 8 + 8) + 2 * Self::SIZE + 1 + 7;
+
+    pub fn reset(&mut self, season: u64) {
+        self.season = season;
+        self.by_volume = [RankEntry::default(); 16];
+        self.by_velocity = [RankEntry::default(); 16];
+    }
+
+    pub fn update(&mut self, leaderboard: &TraderLeaderboard, now: i64) {
+        let hour = now / 3600;
+        rerank(&mut self.by_volume, leaderboard.user, leaderboard.trade_volume, hour);
+        rerank(&mut self.by_velocity, leaderboard.user, leaderboard.velocity_score, hour);
+    }
+
+    /// Split `pot` across the velocity ranking at `now` by the prize curve. Entries that
+    /// dropped out of the rolling window are skipped and the ranks below move up.
+    pub fn payouts(&self, pot: u64, now: i64) -> ([Pubkey; 16], [u64; 16]) {
+        let hour = now / 3600;
+        let mut winners = [Pubkey::default(); 16];
+        let mut prizes = [0u64; 16];
+        let live = self
+            .by_velocity
+            .iter()
+            .filter(|entry| entry.trader != Pubkey::default() && hour - entry.hour < LEADERBOARD_HOURS as i64);
+        for (rank, entry) in live.enumerate() {
+            winners[rank] = entry.trader;
+            prizes[rank] = bps_of(pot, self.prize_curve_bps[rank] as u64);
+        }
+        (winners, prizes)
+    }
+}
+
+/// Prize pool of one leaderboard season. Finalizing snapshots the winners and their prizes.
+#[account]
+pub struct PrizeWindow {
+    pub state: Pubkey,
+    pub season: u64,
+    pub vault: Pubkey,
+    pub finalized: bool,
+    pub finalized_at: i64,
+    pub winners: [Pubkey; 16],
+    pub prizes: [u64; 16],
+    pub claimed: u16, // Bitmask by rank.
+    pub bump: u8,
+}
+
+impl PrizeWindow {
+    pub const LEN: usize = 32 + 8 + 32 + 1 + 8 + 16 * 32 + 16 * 8 + 2 + 1;
+}
+
+#[derive(Accounts)]
+#[instruction(season: u64)]
+pub struct OpenPrizeWindow<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = lvt_mint)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PrizeWindow::LEN,
+        seeds = [b"prize_window", state.key().as_ref(), &season.to_le_bytes()],
+        bump
+    )]
+    pub prize_window: Account<'info, PrizeWindow>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"prize_vault", state.key().as_ref(), &season.to_le_bytes()],
+        bump,
+        token::mint = lvt_mint,
+        token::authority = treasury_authority
+    )]
+    pub prize_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub lvt_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeLeaderboardWindow<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        constraint = keeper.keeper_registry.state == state.key() @ CustomError::InvalidKeeperRegistry
+    )]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"leaderboard_top", state.key().as_ref()], bump = leaderboard_top_n.load()?.bump)]
+    pub leaderboard_top_n: AccountLoader<'info, LeaderboardTopN>,
+    #[account(
+        mut,
+        seeds = [b"prize_window", state.key().as_ref(), &state.leaderboard_season.to_le_bytes()],
+        bump = prize_window.bump
+    )]
+    pub prize_window: Account<'info, PrizeWindow>,
+    #[account(mut, address = prize_window.vault @ CustomError::InvalidPrizeVault)]
+    pub prize_vault: Account<'info, TokenAccount>,
+    #[account(
+        seeds = [b"prize_window", state.key().as_ref(), &(state.leaderboard_season + 1).to_le_bytes()],
+        bump = next_prize_window.bump
+    )]
+    pub next_prize_window: Option<Account<'info, PrizeWindow>>,
+    #[account(mut)]
+    pub next_prize_vault: Option<Account<'info, TokenAccount>>,
+    pub keeper: KeeperBounty<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimLeaderboardPrize<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"prize_window", state.key().as_ref(), &prize_window.season.to_le_bytes()],
+        bump = prize_window.bump
+    )]
+    pub prize_window: Account<'info, PrizeWindow>,
+    #[account(mut, address = prize_window.vault @ CustomError::InvalidPrizeVault)]
+    pub prize_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = state.lvt_mint)]
+    pub winner_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub winner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetPrizeCurve<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"leaderboard_top", state.key().as_ref()], bump = leaderboard_top_n.load()?.bump)]
+    pub leaderboard_top_n: AccountLoader<'info, LeaderboardTopN>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleStrategyBoost<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
     #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
     pub user_state: Account<'info, UserState>,
 }
 
+/// Supported external order book account layouts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BookLayout {
+    OpenBookSlab,
+}
+
+/// The external order book a market trades on.
+#[account]
+pub struct OrderBookSource {
+    pub market: Pubkey,
+    pub layout: BookLayout,
+    pub dex_program: Pubkey, // Owner of the book and open orders accounts.
+    pub dex_market: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    // Orders up to aged_seq were placed at least GAP_MIN_ORDER_AGE ago. pending_seq is the newest
+    // sequence number seen at pending_since and becomes aged_seq once that is old enough.
+    pub aged_seq: u64,
+    pub pending_seq: u64,
+    pub pending_since: i64,
+    pub bump: u8,
+}
+
+impl OrderBookSource {
+    pub const LEN: usize = 32 + 1 + 32 * 4 + 8 + 8 + 8 + 1;
+
+    /// Advance the order age watermark with the newest sequence number seen in the book.
+    pub fn observe_seq(&mut self, latest_seq: u64, now: i64) {
+        if now >= self.pending_since.checked_add(GAP_MIN_ORDER_AGE).unwrap() {
+            self.aged_seq = self.aged_seq.max(self.pending_seq);
+            self.pending_seq = latest_seq;
+            self.pending_since = now;
+        }
+    }
+}
+
 #[derive(Accounts)]
-pub struct ClaimRewards<'info> {
+pub struct InitOrderBookSource<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + OrderBookSource::LEN,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump
+    )]
+    pub order_book_source: Account<'info, OrderBookSource>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreditGapReduction<'info> {
+    #[account(mut, seeds = [b"order_book", order_book_source.market.as_ref()], bump = order_book_source.bump)]
+    pub order_book_source: Account<'info, OrderBookSource>,
+    /// CHECK: Parsed according to order_book_source.layout.
+    #[account(address = order_book_source.bids, owner = order_book_source.dex_program)]
+    pub bids: UncheckedAccount<'info>,
+    /// CHECK: Parsed according to order_book_source.layout.
+    #[account(address = order_book_source.asks, owner = order_book_source.dex_program)]
+    pub asks: UncheckedAccount<'info>,
+    /// CHECK: The maker's open orders account; its owner must be user_state.owner.
+    #[account(owner = order_book_source.dex_program)]
+    pub open_orders: UncheckedAccount<'info>,
     #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
     pub user_state: Account<'info, UserState>,
+}
+
+/// Market-maker quoting rewards on one market, scored per reward epoch.
+#[account]
+pub struct MmProgram {
+    pub state: Pubkey,
+    pub market: Pubkey,
+    pub attester: Pubkey,         // Submits quote snapshots.
+    pub max_spread_bps: u16,      // Wider quotes score zero.
+    pub depth_band_bps: u16,      // Depth is counted within this distance of mid.
+    pub snapshot_interval: i64,   // Seconds between snapshots of the same maker.
+    pub epoch_budget: u64,        // LVT paid per epoch from the traders/LP bucket.
+    pub epoch: u64,
+    pub epoch_score: u128,        // Sum of all makers' scores in `epoch`.
+    // The last finished epoch, which makers claim against:
+    pub prev_epoch: u64,
+    pub prev_score: u128,
+    pub prev_budget: u64,
+    pub bump: u8,
+}
+
+impl MmProgram {
+    pub const LEN: usize = 32 * 3 + 2 + 2 + 8 + 8 + 8 + 16 + 8 + 16 + 8 + 1;
+
+    pub fn roll(&mut self, epoch: u64) {
+        if self.epoch != epoch {
+            self.prev_epoch = self.epoch;
+            self.prev_score = self.epoch_score;
+            self.prev_budget = self.epoch_budget;
+            self.epoch = epoch;
+            self.epoch_score = 0;
+        }
+    }
+}
+
+#[account]
+pub struct MmScore {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub epoch: u64,
+    pub score: u128,
+    pub snapshots: u32,           // Snapshots taken this epoch.
+    pub quoted: u32,              // Of which the maker was quoting within max spread.
+    pub last_round: i64,          // Last snapshot, in units of snapshot_interval.
+    pub pending_epoch: u64,       // Finished epoch with an unclaimed score.
+    pub pending_score: u128,
+    pub bump: u8,
+}
+
+impl MmScore {
+    pub const LEN: usize = 32 + 32 + 8 + 16 + 4 + 4 + 8 + 8 + 16 + 1;
+
+    pub fn roll(&mut self, epoch: u64) {
+        if self.epoch != epoch {
+            self.pending_epoch = self.epoch;
+            self.pending_score = self.score;
+            self.epoch = epoch;
+            self.score = 0;
+            self.snapshots = 0;
+            self.quoted = 0;
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitMmProgram<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"market", state.key().as_ref(), market.pair.as_bytes()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + MmProgram::LEN,
+        seeds = [b"mm_program", market.key().as_ref()],
+        bump
+    )]
+    pub mm_program: Account<'info, MmProgram>,
     #[account(mut)]
-    pub treasury: Account<'info, TokenAccount>,
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMmProgram<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
+    pub state: Account<'info, State>,
+    #[account(mut, has_one = state, seeds = [b"mm_program", mm_program.market.as_ref()], bump = mm_program.bump)]
+    pub mm_program: Account<'info, MmProgram>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(maker: Pubkey)]
+pub struct InitMmScore<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(has_one = state, seeds = [b"mm_program", mm_program.market.as_ref()], bump = mm_program.bump)]
+    pub mm_program: Account<'info, MmProgram>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + MmScore::LEN,
+        seeds = [b"mm_score", mm_program.market.as_ref(), maker.as_ref()],
+        bump
+    )]
+    pub mm_score: Account<'info, MmScore>,
     #[account(mut)]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitQuoteSnapshot<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        has_one = state,
+        has_one = attester @ CustomError::Unauthorized,
+        seeds = [b"mm_program", mm_program.market.as_ref()],
+        bump = mm_program.bump
+    )]
+    pub mm_program: Account<'info, MmProgram>,
+    #[account(mut, seeds = [b"mm_score", mm_program.market.as_ref(), mm_score.maker.as_ref()], bump = mm_score.bump)]
+    pub mm_score: Account<'info, MmScore>,
+    pub attester: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimMmReward<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, has_one = state, seeds = [b"mm_program", mm_program.market.as_ref()], bump = mm_program.bump)]
+    pub mm_program: Account<'info, MmProgram>,
+    #[account(
+        mut,
+        has_one = maker,
+        seeds = [b"mm_score", mm_program.market.as_ref(), maker.key().as_ref()],
+        bump = mm_score.bump
+    )]
+    pub mm_score: Account<'info, MmScore>,
+    #[account(
+        mut,
+        seeds = [b"bucket", state.key().as_ref(), &[BUCKET_TRADERS_LPS]],
+        bump = treasury_bucket.bump
+    )]
+    pub treasury_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = treasury_bucket.vault)]
+    pub bucket_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = state.lvt_mint)]
+    pub maker_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every bucket vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub maker: Signer<'info>,
     pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct UpdatePoolFees<'info> {
-    #[account(mut)]
+pub struct BatchProcessTrades<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"shuffle", state.key().as_ref(), shuffle_batch.batch_id.to_le_bytes().as_ref()],
+        bump = shuffle_batch.bump,
+        has_one = committer
+    )]
+    pub shuffle_batch: Account<'info, ShuffleBatch>,
+    /// CHECK: SlotHashes sysvar, parsed manually because it is too large to deserialize on-chain.
+    #[account(address = anchor_lang::solana_program::sysvar::slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
+    /// CHECK: VRF result account; checked against the batch and State.vrf_program when used.
+    pub vrf_randomness: Option<UncheckedAccount<'info>>,
+    pub committer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTradeVenue<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized
+    )]
     pub state: Account<'info, State>,
     pub admin: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct AutoAdjustFee<'info> {
-    #[account(mut)]
+pub struct SetVrfProgram<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized
+    )]
+    pub state: Account<'info, State>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CommitShuffleSeed<'info> {
+    #[account(mut, seeds = [b"state"], bump = state.bump)]
     pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = committer,
+        space = 8 + ShuffleBatch::LEN,
+        seeds = [b"shuffle", state.key().as_ref(), state.shuffle_batch_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub shuffle_batch: Account<'info, ShuffleBatch>,
+    #[account(mut)]
+    pub committer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RandomnessSource {
+    SlotHashes,
+    Vrf,
+}
+
+/// Randomized execution order for one batch of trades.
+#[account]
+pub struct ShuffleBatch {
+    pub state: Pubkey,
+    pub batch_id: u64,
+    pub committer: Pubkey,
+    pub item_count: u16,
+    pub seed_commitment: [u8; 32],
+    pub commit_slot: u64,
+    pub source: RandomnessSource,
+    pub vrf_account: Pubkey,     // Only used with RandomnessSource::Vrf.
+    pub randomness: [u8; 32],    // Final mixed randomness, for verification.
+    pub permutation: Vec<u16>,   // Execution order: permutation[k] is the index of the k-th trade.
+    pub processed: bool,
+    pub bump: u8,
+}
+
+impl ShuffleBatch {
+    pub const MAX_ITEMS: usize = 64;
+    pub const LEN: usize = 32 + 8 + 32 + 2 + 32 + 8 + 1 + 32 + 32 + 4 + Self::MAX_ITEMS * 2 + 1 + 1;
+}
+
+#[derive(Accounts)]
+pub struct BorrowAgainstLVT<'info> {
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump, has_one = owner)]
+    pub user_state: Account<'info, UserState>,
+    #[account(init, payer = owner, space = 8 + LoanAccount::LEN)]
+    pub loan_account: Account<'info, LoanAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+pub struct LoanAccount {
+    pub borrower: Pubkey,
+    pub collateral: u64,
+    pub borrow_amount: u64,
+    pub interest_rate: u64,
+    pub start_time: i64,
+    pub due_time: i64,
+    pub bump: u8,
+    pub liquidated: bool,
+}
+
+impl LoanAccount {
+    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 8 + 1 + 1;
+}
+
+/// Loans must stay collateralized by at least this percentage of the borrowed amount.
+pub const LOAN_COLLATERAL_PCT: u128 = 150;
+
+/// Exact check shared by borrowing and liquidation: `staked * 100 >= borrowed * 150`.
+pub fn collateral_covers(staked: u64, borrowed: u64) -> bool {
+    (staked as u128) * 100 >= (borrowed as u128) * LOAN_COLLATERAL_PCT
 }
 
 #[derive(Accounts)]
-pub struct BatchTradingOrders<'info> {
+pub struct LiquidateLoan<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump = state.bump,
+        constraint = keeper.keeper_registry.state == state.key() @ CustomError::InvalidKeeperRegistry
+    )]
+    pub state: Account<'info, State>,
     #[account(mut)]
+    pub loan_account: Account<'info, LoanAccount>,
+    #[account(seeds = [b"user", loan_account.borrower.as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    pub keeper: KeeperBounty<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitKeeperRegistry<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized,
+        has_one = lvt_mint
+    )]
     pub state: Account<'info, State>,
-    // Additional accounts for order queuing can be added.
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + KeeperRegistry::LEN,
+        seeds = [b"keeper", state.key().as_ref()],
+        bump
+    )]
+    pub keeper_registry: Account<'info, KeeperRegistry>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"keeper_vault", state.key().as_ref()],
+        bump,
+        token::mint = lvt_mint,
+        token::authority = treasury_authority
+    )]
+    pub keeper_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub lvt_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateFeeByVote<'info> {
-    #[account(mut)]
+pub struct SetKeeperCrank<'info> {
+    #[account(seeds = [b"state"], bump = state.bump, has_one = admin @ CustomError::Unauthorized)]
     pub state: Account<'info, State>,
-    #[account(mut)]
-    pub governance: Account<'info, GovernanceVote>,
+    #[account(mut, seeds = [b"keeper", state.key().as_ref()], bump = keeper_registry.bump)]
+    pub keeper_registry: Account<'info, KeeperRegistry>,
     pub admin: Signer<'info>,
 }
 
-#[account]
-pub struct GovernanceVote {
-    pub vote_count: u64,
-    pub required_votes: u64,
+/// Accounts every keeper crank takes to enforce its interval and pay the caller's bounty.
+#[derive(Accounts)]
+pub struct KeeperBounty<'info> {
+    #[account(mut, seeds = [b"keeper", keeper_registry.state.as_ref()], bump = keeper_registry.bump)]
+    pub keeper_registry: Account<'info, KeeperRegistry>,
+    #[account(mut, address = keeper_registry.vault)]
+    pub keeper_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = keeper_vault.mint)]
+    pub keeper_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every program vault.
+    #[account(
+        seeds = [b"treasury", keeper_registry.state.as_ref()],
+        bump = keeper_registry.treasury_authority_bump
+    )]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub keeper: Signer<'info>,
+    pub token_program: Program<'info, Token>,
 }
 
-impl GovernanceVote {
-    pub const LEN: usize = 16; // 8+8
+impl<'info> KeeperBounty<'info> {
+    /// Record a run of `kind` and pay its bounty, or whatever is left in the vault.
+    pub fn pay(&mut self, kind: CrankKind, now: i64) -> Result<()> {
+        let bounty = self.keeper_registry.record_run(kind, now)?.min(self.keeper_vault.amount);
+        if bounty > 0 {
+            treasury_transfer(
+                &self.token_program,
+                &self.keeper_vault,
+                &self.keeper_token_account,
+                &self.treasury_authority.to_account_info(),
+                &self.keeper_registry.state,
+                self.keeper_registry.treasury_authority_bump,
+                bounty,
+            )?;
+            self.keeper_registry.total_paid = self.keeper_registry.total_paid.checked_add(bounty).unwrap();
+        }
+        emit!(KeeperPaid {
+            keeper: self.keeper.key(),
+            kind,
+            bounty,
+        });
+        Ok(())
+    }
 }
 
-#[derive(Accounts)]
-pub struct UpdateDynamicReward<'info> {
-    #[account(mut)]
-    pub state: Account<'info, State>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CrankKind {
+    FeeAdjust,
+    EpochRollover,
+    BatchProcess,
+    LeaderboardReset,
+    LoanLiquidation,
 }
 
-#[derive(Accounts)]
-pub struct UpdateLeaderboard<'info> {
-    #[account(mut, seeds = [b"leaderboard", user.key().as_ref()], bump = leaderboard.bump)]
-    pub leaderboard: Account<'info, TraderLeaderboard>,
-    pub user: Signer<'info>,
+impl CrankKind {
+    /// Cranks that advance protocol-wide state run at most once per interval. Batch and
+    /// liquidation cranks act on one queue or loan and may run any time; their interval only
+    /// spaces out the bounties.
+    pub fn gates_state(self) -> bool {
+        matches!(self, CrankKind::FeeAdjust | CrankKind::EpochRollover | CrankKind::LeaderboardReset)
+    }
 }
 
+/// Keeper bounties and run intervals, indexed by CrankKind.
 #[account]
-pub struct TraderLeaderboard {
-    pub user: Pubkey,
-    pub trade_volume: u64,
-    pub trade_count: u64,
-    pub last_update: i64,
+pub struct KeeperRegistry {
+    pub state: Pubkey,
+    pub vault: Pubkey,
+    pub bounties: [u64; 5],  // LVT base units paid per run.
+    pub intervals: [i64; 5], // Minimum seconds between runs (or paid runs); 0 = every call.
+    pub last_run: [i64; 5],
+    pub total_paid: u64,
+    pub treasury_authority_bump: u8,
     pub bump: u8,
 }
 
-impl TraderLeaderboard {
-    pub const LEN: usize = 32 + 8 + 8 + 8 + 1;
-}
+impl KeeperRegistry {
+    // Fee adjust hourly, daily epochs, batches every 10s, daily leaderboard windows, hourly
+    // liquidation bounties (loans move no tokens, so the bounty must not be farmable per call).
+    pub const DEFAULT_INTERVALS: [i64; 5] = [3600, 86400, 10, 86400, 3600];
+    pub const LEN: usize = 32 + 32 + 5 * 8 + 5 * 8 + 5 * 8 + 8 + 1 + 1;
 
-#[derive(Accounts)]
-pub struct RewardStrategyBoost<'info> {
-    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
-    pub user_state: Account<'info, UserState>,
+    /// Enforce the crank's interval and mark it run; returns the bounty due. A crank that does
+    /// not gate state may run early, unpaid.
+    pub fn record_run(&mut self, kind: CrankKind, now: i64) -> Result<u64> {
+        let i = kind as usize;
+        if self.last_run[i] != 0 && now < self.last_run[i] + self.intervals[i] {
+            require!(!kind.gates_state(), CustomError::CrankTooEarly);
+            return Ok(0);
+        }
+        self.last_run[i] = now;
+        Ok(self.bounties[i])
+    }
 }
 
 #[derive(Accounts)]
-pub struct BatchProcessTrades<'info> {
-    #[account(mut)]
+#[instruction(bucket: u8)]
+pub struct InitTreasuryBucket<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump = state.bump,
+        has_one = admin @ CustomError::Unauthorized,
+        has_one = lvt_mint
+    )]
     pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + TreasuryBucket::LEN,
+        seeds = [b"bucket", state.key().as_ref(), &[bucket]],
+        bump
+    )]
+    pub treasury_bucket: Account<'info, TreasuryBucket>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"bucket_vault", state.key().as_ref(), &[bucket]],
+        bump,
+        token::mint = lvt_mint,
+        token::authority = treasury_authority
+    )]
+    pub bucket_vault: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every bucket vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    pub lvt_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct BorrowAgainstLVT<'info> {
-    #[account(mut, seeds = [b"user", user_state.owner.as_ref()], bump = user_state.bump)]
-    pub user_state: Account<'info, UserState>,
-    #[account(init, payer = borrower, space = 8 + LoanAccount::LEN)]
-    pub loan_account: Account<'info, LoanAccount>,
-    #[account(mut)]
-    pub borrower: Signer<'info>,
-    pub system_program: Program<'info, System>,
+pub struct DistributeTreasury<'info> {
+    #[account(seeds = [b"state"], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, address = state.treasury)]
+    pub treasury: Account<'info, TokenAccount>,
+    /// CHECK: PDA that owns the treasury and every bucket vault.
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = state.treasury_authority_bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_TRADERS_LPS]], bump = traders_lps_bucket.bump)]
+    pub traders_lps_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = traders_lps_bucket.vault)]
+    pub traders_lps_vault: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_STAKING]], bump = staking_bucket.bump)]
+    pub staking_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = staking_bucket.vault)]
+    pub staking_vault: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_DEVELOPMENT]], bump = development_bucket.bump)]
+    pub development_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = development_bucket.vault)]
+    pub development_vault: Account<'info, TokenAccount>,
+    #[account(mut, seeds = [b"bucket", state.key().as_ref(), &[BUCKET_ECOSYSTEM]], bump = ecosystem_bucket.bump)]
+    pub ecosystem_bucket: Account<'info, TreasuryBucket>,
+    #[account(mut, address = ecosystem_bucket.vault)]
+    pub ecosystem_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[account]
-pub struct LoanAccount {
-    pub borrower: Pubkey,
-    pub collateral: u64,
-    pub borrow_amount: u64,
-    pub interest_rate: u64,
-    pub start_time: i64,
-    pub due_time: i64,
+pub struct TreasuryBucket {
+    pub state: Pubkey,
+    pub bucket: u8,
+    pub vault: Pubkey,
+    pub total_distributed: u64,
+    pub total_spent: u64,
     pub bump: u8,
 }
 
-impl LoanAccount {
-    pub const LEN: usize = 32 + 8 + 8 + 8 + 8 + 8 + 1;
+impl TreasuryBucket {
+    pub const LEN: usize = 32 + 1 + 32 + 8 + 8 + 1;
 }
 
 #[account]
@@ -534,17 +5227,368 @@ pub struct UserState {
     pub reward_multiplier: u64,
     pub trade_count: u64,
     pub cumulative_volume: u64,
-    pub fee_discount: u64,
-    pub lockup_end: i64,           // Timestamp when lockup expires; 0 if none.
+    pub fee_discount: u64,         // Staking tier fee discount, in basis points.
+    pub lockup_end: i64,           // Stake-weighted average lockup end of locked positions; 0 if none.
     pub is_institutional: bool,    // Whitelist flag for institutional traders.
     pub last_claim_time: i64,      // For cooldown on claims.
-    pub trading_rebate: u64,       // Additional reward for staking tier.
+    pub trading_rebate: u64,       // Staking tier trading rebate, in basis points.
     pub bump: u8,
+    pub execution_priority: bool,  // Staking tier execution priority.
+    pub tier_boost_bps: u64,       // Staking tier reward boost; 0 until a tier has been resolved.
+    pub position_count: u64,       // Number of StakePositions opened; next position index.
+    pub weighted_stake: u64,       // Sum of staked positions weighted by lockup yield multiplier.
+    pub staking_reward_debt: u128, // StakingPool accumulator already accounted for at the current weight.
+    pub pending_staking_yield: u64, // Settled staking yield waiting to be harvested.
+    pub delegate: Pubkey,           // Who this user's voting power is delegated to; default = nobody.
+    // veLVT delegated to this user, as aggregates so it can be evaluated at any time:
+    pub delegated_amount: u64,        // Sum of delegated stake.
+    pub delegated_lock_weight: u128,  // Sum of delegated stake * its lockup_end.
+    // Strategy stats for reward epoch `strategy_epoch`, from record_trade fills:
+    pub strategy_epoch: u64,
+    pub epoch_volume: u64,
+    pub maker_volume: u64,
+    pub maker_bids: u32,              // Maker fills on each side; market making needs both.
+    pub maker_asks: u32,
+    pub arb_volume: u64,              // Volume that moved the price toward another market's.
+    pub arb_trades: u32,
+    pub strategy_flags: u8,           // Classification of the last settled epoch (STRATEGY_*).
+    pub strategy_boost_total: u64,
+    pub gap_credit_bps: u64,          // Total order book gap reduction credited.
+    pub last_gap_credit: i64,
+    pub counterparties: [CounterpartyEntry; COUNTERPARTY_LRU_SIZE], // Most recent counterparties.
+    pub flagged_trades: u64,          // Trades flagged as suspected wash trades.
+    // Reputation is BPS_DENOMINATOR minus a penalty that decays; zero penalty is a full reputation.
+    pub reputation_penalty: u64,      // As of reputation_updated_at.
+    pub reputation_updated_at: i64,
+    pub cancel_window_start: i64,
+    pub window_cancels: u32,
+    // veLVT of the user's own positions, as aggregates over positions staked with a lockup:
+    pub locked_amount: u64,           // Sum of locked stake.
+    pub lock_weight: u128,            // Sum of locked stake * its lockup_end.
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct CounterpartyEntry {
+    pub counterparty: Pubkey,
+    pub pair: [u8; 8],  // pair_key of the last trade.
+    pub side: u8,       // OrderSide of the last trade.
+    pub count: u32,     // Trades within WASH_WINDOW of each other; 0 = empty.
+    pub last_seen: i64,
+}
+
+impl CounterpartyEntry {
+    pub const LEN: usize = 32 + 8 + 1 + 4 + 8;
 }
 
 impl UserState {
     // Calculation: 32 + (8*7) + 8 + 1 + 8 + 8 + 1 = 32 + 56 + 8 + 1 + 8 + 8 + 1 = 114 bytes.
-    pub const LEN: usize = 114;
+    // Tier: execution_priority (1) + tier_boost_bps (8).
+    // Positions: position_count (8) + weighted_stake (8).
+    // Staking yield: staking_reward_debt (16) + pending_staking_yield (8).
+    // Delegation: delegate (32) + delegated_amount (8) + delegated_lock_weight (16).
+    // Strategy: 4 * u64 (32) + 3 * u32 (12) + flags (1) + strategy_boost_total (8).
+    // Gap credit: gap_credit_bps (8) + last_gap_credit (8).
+    // Wash detection: counterparty LRU + flagged_trades (8).
+    // Reputation: penalty (8) + updated_at (8) + cancel_window_start (8) + window_cancels (4).
+    // Locks: locked_amount (8) + lock_weight (16).
+    pub const LEN: usize =
+        114 + 1 + 8 + 16 + 24 + 56 + 53 + 16 + COUNTERPARTY_LRU_SIZE * CounterpartyEntry::LEN + 8 + 28 + 24;
+
+    /// Reward boost from the staking tier, neutral if no tier has been resolved yet.
+    pub fn tier_boost(&self) -> u64 {
+        if self.tier_boost_bps == 0 { BPS_DENOMINATOR } else { self.tier_boost_bps }
+    }
+
+    /// veLVT of the user's own locked positions at `now`. Positions whose lock expired undercount
+    /// until they are unstaked, never overcount.
+    pub fn ve_power(&self, now: i64) -> u64 {
+        ve_power(self.locked_amount, self.lockup_end, now)
+    }
+
+    /// Add a new position's lock to the veLVT aggregates.
+    pub fn add_lock(&mut self, position: &StakePosition) {
+        if position.lockup_end > position.lockup_start {
+            self.locked_amount = self.locked_amount.checked_add(position.amount).unwrap();
+            self.lock_weight = self
+                .lock_weight
+                .checked_add(position.amount as u128 * position.lockup_end as u128)
+                .unwrap();
+            self.refresh_lockup_end();
+        }
+    }
+
+    /// Remove a detached position's lock from the veLVT aggregates.
+    pub fn remove_lock(&mut self, position: &StakePosition) {
+        if position.lockup_end > position.lockup_start {
+            self.locked_amount = self.locked_amount.saturating_sub(position.amount);
+            self.lock_weight = self
+                .lock_weight
+                .saturating_sub(position.amount as u128 * position.lockup_end as u128);
+            self.refresh_lockup_end();
+        }
+    }
+
+    fn refresh_lockup_end(&mut self) {
+        self.lockup_end = match self.locked_amount {
+            0 => 0,
+            locked => (self.lock_weight / locked as u128) as i64,
+        };
+    }
+
+    pub fn credit_delegation(&mut self, delegation: &Delegation) {
+        self.delegated_amount = self.delegated_amount.checked_add(delegation.amount).unwrap();
+        self.delegated_lock_weight = self
+            .delegated_lock_weight
+            .checked_add(delegation.amount as u128 * delegation.lockup_end as u128)
+            .unwrap();
+    }
+
+    pub fn debit_delegation(&mut self, delegation: &Delegation) {
+        self.delegated_amount = self.delegated_amount.saturating_sub(delegation.amount);
+        self.delegated_lock_weight = self
+            .delegated_lock_weight
+            .saturating_sub(delegation.amount as u128 * delegation.lockup_end as u128);
+    }
+
+    pub fn record_fill(&mut self, amount: u64, side: OrderSide, liquidity: LiquiditySide, price: u64, reference_price: u64) {
+        self.epoch_volume = self.epoch_volume.checked_add(amount).unwrap();
+        if liquidity == LiquiditySide::Maker {
+            self.maker_volume = self.maker_volume.checked_add(amount).unwrap();
+            match side {
+                OrderSide::Bid => self.maker_bids = self.maker_bids.saturating_add(1),
+                OrderSide::Ask => self.maker_asks = self.maker_asks.saturating_add(1),
+            }
+        }
+        if converges(side, price, reference_price) {
+            self.arb_volume = self.arb_volume.checked_add(amount).unwrap();
+            self.arb_trades = self.arb_trades.saturating_add(1);
+        }
+    }
+
+    /// Reputation at `now` in bps, from 0 to BPS_DENOMINATOR.
+    pub fn reputation_bps(&self, now: i64) -> u64 {
+        BPS_DENOMINATOR - self.decayed_penalty(now)
+    }
+
+    fn decayed_penalty(&self, now: i64) -> u64 {
+        let elapsed = now.saturating_sub(self.reputation_updated_at).max(0) as u128;
+        let recovered = elapsed * REPUTATION_RECOVERY_BPS_PER_DAY as u128 / 86400;
+        (self.reputation_penalty as u128).saturating_sub(recovered) as u64
+    }
+
+    pub fn penalize(&mut self, penalty_bps: u64, now: i64) {
+        self.reputation_penalty = self.decayed_penalty(now).saturating_add(penalty_bps).min(BPS_DENOMINATOR);
+        self.reputation_updated_at = now;
+    }
+
+    /// Count a cancel, penalizing each one beyond CANCEL_SPAM_LIMIT within CANCEL_SPAM_WINDOW.
+    pub fn record_cancel(&mut self, now: i64) {
+        if now - self.cancel_window_start >= CANCEL_SPAM_WINDOW {
+            self.cancel_window_start = now;
+            self.window_cancels = 0;
+        }
+        self.window_cancels = self.window_cancels.saturating_add(1);
+        if self.window_cancels > CANCEL_SPAM_LIMIT {
+            self.penalize(CANCEL_SPAM_PENALTY_BPS, now);
+        }
+    }
+
+    /// Track a trade against `counterparty` in the LRU and return the WASH_* signals it raises:
+    /// too many trades with them in a row, or reversing the last trade with them on the pair.
+    pub fn observe_counterparty(&mut self, counterparty: Pubkey, pair: [u8; 8], side: OrderSide, now: i64) -> u8 {
+        let index = self
+            .counterparties
+            .iter()
+            .position(|entry| entry.count > 0 && entry.counterparty == counterparty)
+            // Otherwise evict the least recently seen entry; empty entries go first.
+            .unwrap_or_else(|| {
+                (0..COUNTERPARTY_LRU_SIZE)
+                    .min_by_key(|&i| (self.counterparties[i].count > 0, self.counterparties[i].last_seen))
+                    .unwrap()
+            });
+        let entry = &mut self.counterparties[index];
+        let mut flags = 0;
+        if entry.count > 0 && entry.counterparty == counterparty && now - entry.last_seen <= WASH_WINDOW {
+            if entry.pair == pair && entry.side != side as u8 {
+                flags |= WASH_ROUND_TRIP;
+            }
+            entry.count = entry.count.saturating_add(1);
+        } else {
+            entry.counterparty = counterparty;
+            entry.count = 1;
+        }
+        if entry.count > WASH_REPEAT_LIMIT {
+            flags |= WASH_REPEAT_COUNTERPARTY;
+        }
+        entry.pair = pair;
+        entry.side = side as u8;
+        entry.last_seen = now;
+        flags
+    }
+
+    /// Once per epoch: classify the stats of a finished epoch and start counting `epoch`.
+    /// Returns the classification and the capped boost it earned.
+    pub fn settle_strategy(&mut self, epoch: u64) -> (u8, u64) {
+        if self.strategy_epoch == epoch {
+            return (0, 0);
+        }
+        let (flags, boost) = classify_strategy(self);
+        self.strategy_epoch = epoch;
+        self.epoch_volume = 0;
+        self.maker_volume = 0;
+        self.maker_bids = 0;
+        self.maker_asks = 0;
+        self.arb_volume = 0;
+        self.arb_trades = 0;
+        self.strategy_flags = flags;
+        self.strategy_boost_total = self.strategy_boost_total.checked_add(boost).unwrap();
+        (flags, boost)
+    }
+}
+
+//
+// EVENTS
+//
+
+#[event]
+pub struct OrderBatchSequenced {
+    pub market: Pubkey,
+    pub batch_id: u64,
+    pub order_ids: Vec<u64>, // In execution order.
+    pub batch_hash: [u8; 32],
+}
+
+#[event]
+pub struct OrderProcessed {
+    pub market: Pubkey,
+    pub batch_id: u64,
+    pub sequence: u64, // Position in the batch's execution order.
+    pub order_id: u64,
+    pub owner: Pubkey,
+    pub side: OrderSide,
+    pub price: u64,
+    pub size: u64,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub market: Pubkey,
+    pub order_id: u64,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct KeeperPaid {
+    pub keeper: Pubkey,
+    pub kind: CrankKind,
+    pub bounty: u64,
+}
+
+#[event]
+pub struct WashTradeFlagged {
+    pub user: Pubkey,
+    pub counterparty: Pubkey,
+    pub flags: u8,
+}
+
+#[event]
+pub struct GapReductionCredited {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub gap_before_bps: u64,
+    pub gap_after_bps: u64,
+    pub reward: u64,
+}
+
+#[event]
+pub struct QuoteSnapshotRecorded {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub round: i64,
+    pub score: u64,
+}
+
+#[event]
+pub struct MarketMakerRewardClaimed {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub epoch: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct StrategyBoostApplied {
+    pub user: Pubkey,
+    pub epoch: u64,
+    pub flags: u8,
+    pub boost: u64,
+}
+
+#[event]
+pub struct LeaderboardWindowFinalized {
+    pub season: u64,
+    pub pot: u64,
+    pub allocated: u64,
+    pub rolled_over: u64,
+}
+
+#[event]
+pub struct LeaderboardPrizeClaimed {
+    pub season: u64,
+    pub winner: Pubkey,
+    pub rank: u8,
+    pub amount: u64,
+}
+
+#[event]
+pub struct EpochRolledOver {
+    pub epoch: u64,
+    pub started_at: i64,
+}
+
+#[event]
+pub struct LoanLiquidated {
+    pub loan: Pubkey,
+    pub borrower: Pubkey,
+    pub borrow_amount: u64,
+}
+
+#[event]
+pub struct ProposalQueued {
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub action: ProposalAction,
+    pub eta: i64,
+}
+
+#[event]
+pub struct ProposalVetoed {
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub proposal: Pubkey,
+    pub id: u64,
+}
+
+#[event]
+pub struct ShuffleBatchProcessed {
+    pub batch_id: u64,
+    pub randomness: [u8; 32],
+    pub permutation: Vec<u16>,
+}
+
+#[event]
+pub struct BatchAuctionSettled {
+    pub market: Pubkey,
+    pub batch_id: u64,
+    pub clearing_price: u64,
+    pub cleared_volume: u64,
+    pub revealed_orders: u64,
+    pub commitments: u64,
 }
 
 //
@@ -566,4 +5610,858 @@ pub enum CustomError {
     MinimumHoldingPeriodNotMet,
     #[msg("Insufficient collateral for borrowing.")]
     InsufficientCollateral,
+    #[msg("Signer is not authorized for this action.")]
+    Unauthorized,
+    #[msg("Treasury bucket index out of range.")]
+    InvalidTreasuryBucket,
+    #[msg("Treasury split must sum to 10,000 basis points.")]
+    InvalidTreasurySplit,
+    #[msg("Treasury must be owned by the program's treasury authority.")]
+    InvalidTreasuryAuthority,
+    #[msg("Treasury has no undistributed balance.")]
+    NothingToDistribute,
+    #[msg("A vesting account is required to claim into vesting.")]
+    VestingAccountRequired,
+    #[msg("Nothing has vested yet.")]
+    NothingVested,
+    #[msg("Vesting schedule has already been accelerated.")]
+    VestingAlreadyAccelerated,
+    #[msg("Stake must cover the locked vesting balance for the accelerated schedule.")]
+    InsufficientStakeForAcceleration,
+    #[msg("Invalid claim or vesting configuration.")]
+    InvalidVestingConfig,
+    #[msg("Invalid staking tier configuration.")]
+    InvalidTierConfig,
+    #[msg("Stake amount must be greater than zero.")]
+    InvalidStakeAmount,
+    #[msg("Lockup duration must be between zero and the maximum veLVT lock.")]
+    InvalidLockupDuration,
+    #[msg("Stake position is already unbonding.")]
+    PositionAlreadyUnbonding,
+    #[msg("Stake position is not unbonding.")]
+    PositionNotUnbonding,
+    #[msg("Lockup has not expired; use emergency_unstake to exit early.")]
+    LockupNotExpired,
+    #[msg("Unbonding period has not ended.")]
+    UnbondingNotComplete,
+    #[msg("Invalid unstaking configuration.")]
+    InvalidUnstakeConfig,
+    #[msg("No staking yield to harvest.")]
+    NothingToHarvest,
+    #[msg("Market pair must be between 1 and 32 bytes.")]
+    InvalidMarketPair,
+    #[msg("Invalid fee promotion.")]
+    InvalidPromotion,
+    #[msg("Order price and size must be greater than zero.")]
+    InvalidOrder,
+    #[msg("Order queue is full.")]
+    OrderQueueFull,
+    #[msg("No queued orders are ready for this batch.")]
+    EmptyBatch,
+    #[msg("Order is not in the queue.")]
+    OrderNotFound,
+    #[msg("Keeper registry does not belong to this program state.")]
+    InvalidKeeperRegistry,
+    #[msg("This crank already ran within its interval.")]
+    CrankTooEarly,
+    #[msg("Loan is neither past due nor undercollateralized.")]
+    LoanNotLiquidatable,
+    #[msg("Voter has no staked LVT.")]
+    NoVotingPower,
+    #[msg("Proposal is not open for voting.")]
+    VotingClosed,
+    #[msg("Voting on this proposal has not ended.")]
+    VotingNotEnded,
+    #[msg("Proposal was already executed.")]
+    ProposalAlreadyExecuted,
+    #[msg("Proposal action is invalid.")]
+    InvalidProposal,
+    #[msg("Proposal is already queued.")]
+    ProposalAlreadyQueued,
+    #[msg("Proposal has not been queued.")]
+    ProposalNotQueued,
+    #[msg("Proposal timelock has not elapsed.")]
+    TimelockActive,
+    #[msg("Proposal timelock has already elapsed.")]
+    TimelockExpired,
+    #[msg("Proposal was vetoed by the guardian.")]
+    ProposalVetoed,
+    #[msg("Accounts required by the proposal action are missing or do not match.")]
+    MissingProposalAccount,
+    #[msg("Protocol is paused.")]
+    ProtocolPaused,
+    #[msg("Voter has already voted on this proposal.")]
+    AlreadyVoted,
+    #[msg("Delegation accounts are missing or do not match the user's delegate.")]
+    MissingDelegation,
+    #[msg("Cannot delegate to yourself or to the current delegate.")]
+    InvalidDelegate,
+    #[msg("Stake history no longer reaches back to the requested slot.")]
+    StakeHistoryUnavailable,
+    #[msg("Institutional traders must supply their institutional profile.")]
+    InstitutionalProfileRequired,
+    #[msg("Institutional terms must expire in the future.")]
+    InvalidInstitutionalTerms,
+    #[msg("This batch auction phase is not open.")]
+    AuctionPhaseClosed,
+    #[msg("Order commitment has already been revealed.")]
+    CommitmentAlreadyRevealed,
+    #[msg("Revealed order does not match its commitment.")]
+    CommitmentMismatch,
+    #[msg("Batch auction has already been settled.")]
+    AuctionAlreadySettled,
+    #[msg("Batch auction has not been settled yet.")]
+    AuctionNotSettled,
+    #[msg("Shuffle batch size must be between 1 and 64.")]
+    InvalidShuffleBatch,
+    #[msg("Shuffle batch has already been processed.")]
+    ShuffleAlreadyProcessed,
+    #[msg("Randomness for this batch is not available yet.")]
+    RandomnessNotAvailable,
+    #[msg("Randomness account does not match the batch or the registered VRF program.")]
+    InvalidRandomnessAccount,
+    #[msg("Prize window is for a past season or already finalized.")]
+    PrizeWindowClosed,
+    #[msg("Prize window has not been finalized.")]
+    PrizeWindowOpen,
+    #[msg("Prize vault does not match the prize window.")]
+    InvalidPrizeVault,
+    #[msg("Prize curve shares must sum to at most 100%.")]
+    InvalidPrizeCurve,
+    #[msg("No unclaimed prize for this trader at this rank.")]
+    NoPrize,
+    #[msg("Market-maker spread, depth band and snapshot interval must be positive.")]
+    InvalidMmParams,
+    #[msg("This maker was already snapshotted in the current interval.")]
+    QuoteTooSoon,
+    #[msg("Quote prices are crossed or do not bracket mid.")]
+    InvalidQuote,
+    #[msg("No market-maker reward to claim for the last finished epoch.")]
+    NoMarketMakerReward,
+    #[msg("Order book account does not match the expected layout or market.")]
+    InvalidOrderBook,
+    #[msg("Gap reduction was already credited to this maker within the interval.")]
+    GapCreditTooSoon,
+    #[msg("Wallet link must be the PDA of two distinct wallets in ascending order.")]
+    InvalidWalletLink,
+    #[msg("Vesting account holds the maximum number of tranches; withdraw first.")]
+    TooManyVestingTranches,
+    #[msg("The slot hash bound to this batch is no longer in the SlotHashes sysvar.")]
+    RandomnessExpired,
+    #[msg("Order commitment bond is below the minimum or does not cover the revealed order.")]
+    InsufficientBond,
+    #[msg("Too many of this owner's orders are already queued.")]
+    TooManyQueuedOrders,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::__private::bytemuck::Zeroable;
+
+    // Mock VRF result account: discriminator, randomness, fulfilled slot.
+    fn mock_vrf_account(randomness: [u8; 32], slot: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 8];
+        data.extend_from_slice(&randomness);
+        data.extend_from_slice(&slot.to_le_bytes());
+        data
+    }
+
+    // OpenBook slab fixture with one leaf of 100 lots per (price, owner, seq), preceded by an
+    // inner node and followed by a freed node, as in a live book.
+    fn openbook_slab_fixture(flags: u64, leaves: &[(u64, Pubkey, u64)]) -> Vec<u8> {
+        let mut nodes = vec![0u8; SLAB_NODE_LEN];
+        nodes[0..4].copy_from_slice(&1u32.to_le_bytes());
+        for (price, owner, seq) in leaves {
+            let seq = if flags == OPENBOOK_FLAG_BIDS { !*seq } else { *seq };
+            let mut leaf = vec![0u8; SLAB_NODE_LEN];
+            leaf[0..4].copy_from_slice(&SLAB_TAG_LEAF.to_le_bytes());
+            leaf[8..24].copy_from_slice(&(((*price as u128) << 64) | seq as u128).to_le_bytes());
+            leaf[24..56].copy_from_slice(owner.as_ref());
+            leaf[56..64].copy_from_slice(&100u64.to_le_bytes());
+            nodes.extend_from_slice(&leaf);
+        }
+        let mut freed = vec![0u8; SLAB_NODE_LEN];
+        freed[0..4].copy_from_slice(&3u32.to_le_bytes());
+        nodes.extend_from_slice(&freed);
+        let node_count = nodes.len() / SLAB_NODE_LEN;
+
+        let mut data = b"serum".to_vec();
+        data.extend_from_slice(&(OPENBOOK_FLAG_INITIALIZED | flags).to_le_bytes());
+        data.extend_from_slice(&(node_count as u64).to_le_bytes()); // bump_index
+        data.extend_from_slice(&[0u8; SLAB_HEADER_LEN - 8]);
+        data.extend_from_slice(&nodes);
+        data.extend_from_slice(&[0u8; SLAB_NODE_LEN]); // Unused capacity.
+        data.extend_from_slice(b"padding");
+        data
+    }
+
+    #[test]
+    fn openbook_gap_reduction_from_slab_fixtures() {
+        let (maker, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let bids = openbook_slab_fixture(
+            OPENBOOK_FLAG_BIDS,
+            &[(990, other, 1), (998, maker, 2), (996, maker, 3), (995, other, 4)],
+        );
+        let asks = openbook_slab_fixture(OPENBOOK_FLAG_ASKS, &[(1_005, other, 5), (1_002, maker, 6)]);
+        let leaves = openbook_leaves(&bids, true).unwrap();
+        assert_eq!(leaves.iter().map(|leaf| leaf.seq).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(leaves[1].quantity, 100);
+        // Without the maker: 995/1005 = 100 bps; with: 998/1002 = 40 bps, from 300 lots that
+        // improve on everyone else's prices.
+        let gap = gap_reduction(BookLayout::OpenBookSlab, &bids, &asks, &maker, 6).unwrap();
+        assert_eq!(
+            gap,
+            BookGap { before_bps: 100, after_bps: 40, maker_size: 300, latest_seq: 6 }
+        );
+        // The ask (seq 6) is too young to count yet: 998/1005 = 69 bps.
+        let gap = gap_reduction(BookLayout::OpenBookSlab, &bids, &asks, &maker, 5).unwrap();
+        assert_eq!((gap.before_bps, gap.after_bps, gap.maker_size), (100, 69, 200));
+        // Nothing of the maker's has aged.
+        let gap = gap_reduction(BookLayout::OpenBookSlab, &bids, &asks, &maker, 1).unwrap();
+        assert_eq!((gap.before_bps, gap.after_bps, gap.maker_size), (100, 100, 0));
+        // A one-sided book is a full gap.
+        let no_asks = openbook_slab_fixture(OPENBOOK_FLAG_ASKS, &[(1_002, maker, 6)]);
+        let gap = gap_reduction(BookLayout::OpenBookSlab, &bids, &no_asks, &maker, 6).unwrap();
+        assert_eq!((gap.before_bps, gap.after_bps), (MAX_BOOK_GAP_BPS, 40));
+        // Sides cannot be swapped.
+        assert!(openbook_leaves(&asks, true).is_err());
+    }
+
+    #[test]
+    fn order_age_watermark_lags_by_the_minimum_age() {
+        let zeroed = vec![0u8; 8 + OrderBookSource::LEN];
+        let mut source = OrderBookSource::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        source.observe_seq(10, 1_000);
+        assert_eq!((source.aged_seq, source.pending_seq), (0, 10));
+        // Orders placed meanwhile do not move the pending watermark until it has aged.
+        source.observe_seq(20, 1_000 + GAP_MIN_ORDER_AGE - 1);
+        assert_eq!((source.aged_seq, source.pending_seq), (0, 10));
+        source.observe_seq(20, 1_000 + GAP_MIN_ORDER_AGE);
+        assert_eq!((source.aged_seq, source.pending_seq), (10, 20));
+    }
+
+    #[test]
+    fn vrf_randomness_must_be_fulfilled_after_commit() {
+        let data = mock_vrf_account([7; 32], 100);
+        assert_eq!(read_vrf_randomness(&data, 99).unwrap(), [7; 32]);
+        assert!(read_vrf_randomness(&data, 100).is_err());
+        assert!(read_vrf_randomness(&data[..40], 99).is_err());
+    }
+
+    #[test]
+    fn slot_hash_is_bound_to_the_first_slot_after_the_commit() {
+        // Newest first; slot 12 was skipped.
+        let entries: [(u64, u8); 4] = [(14, 4), (13, 3), (11, 1), (10, 0)];
+        let mut data = (entries.len() as u64).to_le_bytes().to_vec();
+        for (slot, byte) in entries {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[byte; 32]);
+        }
+        assert_eq!(read_slot_hash_after(&data, 10).unwrap(), [1; 32]);
+        assert_eq!(read_slot_hash_after(&data, 11).unwrap(), [3; 32]);
+        assert_eq!(read_slot_hash_after(&data, 13).unwrap(), [4; 32]);
+        assert!(read_slot_hash_after(&data, 14).is_err());
+        // The oldest entry is the slot right after commit slot 9, but anything before it is gone.
+        assert_eq!(read_slot_hash_after(&data, 9).unwrap(), [0; 32]);
+        assert_eq!(
+            read_slot_hash_after(&data, 8).unwrap_err(),
+            error!(CustomError::RandomnessExpired)
+        );
+    }
+
+    #[test]
+    fn shuffle_is_a_deterministic_permutation() {
+        let randomness = hashv(&[b"seed".as_ref()]).to_bytes();
+        let permutation = shuffled_indices(64, &randomness);
+        let mut sorted = permutation.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..64).collect::<Vec<u16>>());
+        assert_eq!(permutation, shuffled_indices(64, &randomness));
+        assert_ne!(permutation, shuffled_indices(64, &[0; 32]));
+    }
+
+    #[test]
+    fn ve_power_decays_with_remaining_lock() {
+        assert_eq!(ve_power(1_000, VE_MAX_LOCK, 0), 1_000);
+        assert_eq!(ve_power(1_000, VE_MAX_LOCK, VE_MAX_LOCK / 2), 500);
+        assert_eq!(ve_power(1_000, VE_MAX_LOCK, VE_MAX_LOCK), 0);
+        assert_eq!(ve_power(1_000, 100, 200), 0);
+
+        // Aggregated delegations evaluate to the sum of the individual locks.
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut delegate = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        for (amount, lockup_end) in [(600, VE_MAX_LOCK), (400, VE_MAX_LOCK / 2)] {
+            delegate.credit_delegation(&Delegation {
+                delegator: Pubkey::default(),
+                delegate: Pubkey::default(),
+                amount,
+                lockup_end,
+                bump: 0,
+            });
+        }
+        let now = VE_MAX_LOCK / 4;
+        assert_eq!(
+            delegated_ve_power(delegate.delegated_amount, delegate.delegated_lock_weight, now),
+            ve_power(600, VE_MAX_LOCK, now) + ve_power(400, VE_MAX_LOCK / 2, now)
+        );
+    }
+
+    #[test]
+    fn positions_keep_their_own_lockups_for_ve_power() {
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut user_state = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let position = |amount, lockup_end| StakePosition {
+            owner: Pubkey::default(),
+            index: 0,
+            amount,
+            lockup_start: 0,
+            lockup_end,
+            yield_multiplier_bps: 0,
+            bump: 0,
+            unbonding_end: 0,
+        };
+        let long = position(600, VE_MAX_LOCK);
+        let short = position(400, VE_MAX_LOCK / 2);
+        user_state.add_lock(&long);
+        user_state.add_lock(&short);
+        // Unlocked stake earns no veLVT.
+        user_state.add_lock(&position(5_000, 0));
+        assert_eq!(user_state.locked_amount, 1_000);
+        assert_eq!(user_state.ve_power(0), 800);
+        assert_eq!(user_state.ve_power(0), ve_power(600, VE_MAX_LOCK, 0) + ve_power(400, VE_MAX_LOCK / 2, 0));
+
+        // The short lock ending does not extend to the long one, and vice versa.
+        user_state.remove_lock(&short);
+        assert_eq!(user_state.lockup_end, VE_MAX_LOCK);
+        assert_eq!(user_state.ve_power(VE_MAX_LOCK / 2), 300);
+        user_state.remove_lock(&long);
+        assert_eq!((user_state.locked_amount, user_state.lockup_end, user_state.ve_power(0)), (0, 0, 0));
+    }
+
+    #[test]
+    fn ve_boost_scales_with_ve_power_relative_to_volume() {
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut user_state = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        user_state.epoch_volume = 100_000;
+        assert_eq!(ve_boost_bps(&user_state, 0), BPS_DENOMINATOR);
+
+        // 5_000 veLVT covers half of the 10_000 needed for 100_000 of volume.
+        user_state.locked_amount = 5_000;
+        user_state.lockup_end = VE_MAX_LOCK;
+        assert_eq!(ve_boost_bps(&user_state, 0), BPS_DENOMINATOR + VE_MAX_BOOST_BPS / 2);
+        user_state.locked_amount = 50_000;
+        assert_eq!(ve_boost_bps(&user_state, 0), BPS_DENOMINATOR + VE_MAX_BOOST_BPS);
+        // Remaining lock time still counts: half the lock left is half the veLVT.
+        user_state.locked_amount = 10_000;
+        assert_eq!(ve_boost_bps(&user_state, VE_MAX_LOCK / 2), BPS_DENOMINATOR + VE_MAX_BOOST_BPS / 2);
+    }
+
+    #[test]
+    fn stake_history_reads_balances_as_of_a_period() {
+        const DAY: i64 = STAKE_CHECKPOINT_PERIOD;
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut user_state = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let mut history = StakeHistory {
+            owner: Pubkey::default(),
+            truncated: false,
+            checkpoints: Vec::new(),
+            bump: 0,
+        };
+        user_state.staked_amount = 100;
+        history.record(&user_state, 10 * DAY);
+        user_state.staked_amount = 300;
+        history.record(&user_state, 20 * DAY);
+        // Changes later in the same period share its checkpoint.
+        user_state.staked_amount = 50;
+        history.record(&user_state, 20 * DAY + 3600);
+        // Unchanged balances are not checkpointed again.
+        history.record(&user_state, 21 * DAY);
+
+        assert_eq!(history.at(9).unwrap().staked_amount, 0);
+        assert_eq!(history.at(19).unwrap().staked_amount, 100);
+        assert_eq!(history.at(20).unwrap().staked_amount, 50);
+        assert_eq!(history.at(25).unwrap().staked_amount, 50);
+        assert_eq!(history.checkpoints.len(), 2);
+
+        // However often a history is touched, it never refuses a change.
+        for day in 21..(21 + StakeHistory::MAX_CHECKPOINTS as i64) {
+            for hour in 0..24 {
+                user_state.staked_amount += 1;
+                history.record(&user_state, day * DAY + hour * 3600);
+            }
+        }
+        assert_eq!(history.checkpoints.len(), StakeHistory::MAX_CHECKPOINTS);
+        assert!(history.at(19).is_err());
+        assert!(history.at(20).is_err());
+        // The oldest period still readable is older than any open proposal's snapshot.
+        let latest = 20 + StakeHistory::MAX_CHECKPOINTS as i64;
+        let oldest_snapshot = stake_checkpoint_period(latest * DAY - MAX_VOTING_PERIOD) - 1;
+        assert!(history.at(oldest_snapshot - 1).is_ok());
+        assert_eq!(history.at(latest).unwrap().staked_amount, user_state.staked_amount);
+        assert_eq!(history.at(latest - 1).unwrap().staked_amount, user_state.staked_amount - 24);
+    }
+
+    #[test]
+    fn leaderboard_rolls_hourly_buckets_and_ranks() {
+        let zeroed = vec![0u8; 8 + TraderLeaderboard::LEN];
+        let mut leaderboard = TraderLeaderboard::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        leaderboard.record(100, 10 * 3600);
+        leaderboard.record(50, 10 * 3600 + 60);
+        leaderboard.record(30, 21 * 3600);
+        assert_eq!((leaderboard.trade_volume, leaderboard.trade_count), (180, 3));
+        assert_eq!(leaderboard.velocity_score, 180 * 2 / 24);
+        // Hour 10 leaves the window at hour 34.
+        leaderboard.record(20, 34 * 3600);
+        assert_eq!((leaderboard.trade_volume, leaderboard.trade_count), (50, 2));
+
+        let mut ranking = [RankEntry::default(); 16];
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        rerank(&mut ranking, a, 100, 0);
+        rerank(&mut ranking, b, 200, 1);
+        rerank(&mut ranking, a, 300, 2);
+        assert_eq!((ranking[0].trader, ranking[1].trader), (a, b));
+        // Entries older than 24 hours drop out.
+        rerank(&mut ranking, a, 300, 25);
+        assert_eq!(ranking[0].trader, a);
+        assert_eq!(ranking[1].trader, Pubkey::default());
+    }
+
+    #[test]
+    fn strategy_boost_is_earned_once_per_epoch() {
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut user_state = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        user_state.settle_strategy(1);
+        for side in [OrderSide::Bid, OrderSide::Ask] {
+            for _ in 0..MM_MIN_FILLS_PER_SIDE {
+                user_state.record_fill(1_000_000, side, LiquiditySide::Maker, 100, 0);
+            }
+        }
+        // Buying 1% below the reference market converges prices.
+        user_state.record_fill(1_000_000, OrderSide::Bid, LiquiditySide::Taker, 99, 100);
+        assert_eq!(user_state.settle_strategy(1), (0, 0));
+        assert_eq!(user_state.settle_strategy(2), (STRATEGY_MARKET_MAKER, 10_000));
+        assert_eq!(user_state.settle_strategy(3), (0, 0));
+    }
+
+    #[test]
+    fn quote_score_rewards_tight_two_sided_depth() {
+        // 20 bps spread against a 40 bps maximum keeps 75% of the thinner side.
+        assert_eq!(quote_score(9_990, 10_010, 10_000, 500, 800, 40).unwrap(), 375);
+        assert_eq!(quote_score(9_950, 10_050, 10_000, 500, 800, 40).unwrap(), 0);
+        assert_eq!(quote_score(0, 10_010, 10_000, 0, 800, 40).unwrap(), 0);
+        assert!(quote_score(10_010, 9_990, 10_000, 500, 800, 40).is_err());
+    }
+
+    #[test]
+    fn counterparty_lru_flags_round_trips_and_repeats() {
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut user_state = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let (b, c) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pair = pair_key("LVT/USDC");
+        assert_eq!(user_state.observe_counterparty(b, pair, OrderSide::Bid, 100), 0);
+        assert_eq!(user_state.observe_counterparty(c, pair, OrderSide::Bid, 200), 0);
+        assert_eq!(user_state.observe_counterparty(b, pair, OrderSide::Ask, 300), WASH_ROUND_TRIP);
+        // Outside the window the reversal is unrelated.
+        assert_eq!(user_state.observe_counterparty(b, pair, OrderSide::Bid, 300 + WASH_WINDOW + 1), 0);
+        for i in 0..WASH_REPEAT_LIMIT {
+            assert_eq!(user_state.observe_counterparty(c, pair, OrderSide::Bid, 4_000 + i as i64), 0);
+        }
+        assert_eq!(user_state.observe_counterparty(c, pair, OrderSide::Bid, 4_100), WASH_REPEAT_COUNTERPARTY);
+        // New counterparties evict the least recently seen one.
+        for _ in 0..COUNTERPARTY_LRU_SIZE - 1 {
+            user_state.observe_counterparty(Pubkey::new_unique(), pair, OrderSide::Bid, 4_200);
+        }
+        assert!(user_state.counterparties.iter().any(|entry| entry.counterparty == c));
+        assert!(!user_state.counterparties.iter().any(|entry| entry.counterparty == b));
+    }
+
+    #[test]
+    fn reputation_penalties_decay_and_cap() {
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut user_state = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        assert_eq!(user_state.reputation_bps(1_000), BPS_DENOMINATOR);
+        for _ in 0..CANCEL_SPAM_LIMIT + 2 {
+            user_state.record_cancel(1_000);
+        }
+        user_state.penalize(WASH_PENALTY_BPS, 1_000);
+        assert_eq!(user_state.reputation_bps(1_000), 8_800);
+        // Half a day recovers 250 bps.
+        assert_eq!(user_state.reputation_bps(1_000 + 43_200), 9_050);
+        for _ in 0..20 {
+            user_state.penalize(WASH_PENALTY_BPS, 2_000);
+        }
+        assert_eq!(user_state.reputation_bps(2_000), 0);
+    }
+
+    #[test]
+    fn vesting_tranches_keep_their_own_schedules() {
+        let zeroed = vec![0u8; 8 + VestingAccount::LEN];
+        let mut vesting = VestingAccount::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        vesting.add(1_000, 0, 100, 1_000).unwrap();
+        assert_eq!(vesting.withdrawable(99), 0);
+        assert_eq!(vesting.withdrawable(500), 500);
+        // A later claim does not re-lock the first one.
+        vesting.add(1_000, 500, 100, 1_000).unwrap();
+        assert_eq!(vesting.withdrawable(500), 500);
+        assert_eq!(vesting.withdraw(750), 750 + 250);
+        assert_eq!(vesting.withdraw(1_000), 250 + 250);
+        assert_eq!(vesting.tranches.len(), 1);
+        // Acceleration halves what is left of the remaining tranche.
+        assert_eq!(vesting.accelerate(1_000, 5_000), (500, 1_250));
+        assert_eq!(vesting.withdrawable(1_125), 250);
+        assert_eq!(vesting.accelerate(1_125, 5_000), (0, 1_125));
+        assert_eq!(vesting.withdraw(1_250), 500);
+        assert!(vesting.tranches.is_empty());
+    }
+
+    #[test]
+    fn prize_payouts_skip_stale_ranks() {
+        let mut top_n = LeaderboardTopN::zeroed();
+        top_n.prize_curve_bps = DEFAULT_PRIZE_CURVE_BPS;
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        top_n.by_velocity[0] = RankEntry { trader: a, value: 300, hour: 0 };
+        top_n.by_velocity[1] = RankEntry { trader: b, value: 200, hour: 20 };
+        let (winners, prizes) = top_n.payouts(10_000, 25 * 3600);
+        assert_eq!((winners[0], prizes[0]), (b, 3_000));
+        assert_eq!((winners[1], prizes[1]), (Pubkey::default(), 0));
+    }
+
+    #[test]
+    fn keeper_cranks_respect_their_interval() {
+        let mut registry = KeeperRegistry {
+            state: Pubkey::default(),
+            vault: Pubkey::default(),
+            bounties: [5, 0, 0, 0, 7],
+            intervals: [3600, 0, 0, 0, 0],
+            last_run: [0; 5],
+            total_paid: 0,
+            treasury_authority_bump: 0,
+            bump: 0,
+        };
+        assert_eq!(registry.record_run(CrankKind::FeeAdjust, 1_000).unwrap(), 5);
+        assert!(registry.record_run(CrankKind::FeeAdjust, 4_599).is_err());
+        assert_eq!(registry.record_run(CrankKind::FeeAdjust, 4_600).unwrap(), 5);
+        assert_eq!(registry.record_run(CrankKind::LoanLiquidation, 4_600).unwrap(), 7);
+        assert_eq!(registry.record_run(CrankKind::LoanLiquidation, 4_600).unwrap(), 7);
+    }
+
+    #[test]
+    fn per_target_cranks_run_early_without_a_bounty() {
+        let mut registry = KeeperRegistry {
+            state: Pubkey::default(),
+            vault: Pubkey::default(),
+            bounties: [5, 6, 3, 8, 7],
+            intervals: KeeperRegistry::DEFAULT_INTERVALS,
+            last_run: [0; 5],
+            total_paid: 0,
+            treasury_authority_bump: 0,
+            bump: 0,
+        };
+        // Liquidating one loan does not hold off the next; only the first is paid this hour.
+        assert_eq!(registry.record_run(CrankKind::LoanLiquidation, 1_000).unwrap(), 7);
+        assert_eq!(registry.record_run(CrankKind::LoanLiquidation, 1_001).unwrap(), 0);
+        assert_eq!(registry.record_run(CrankKind::LoanLiquidation, 4_599).unwrap(), 0);
+        assert_eq!(registry.record_run(CrankKind::LoanLiquidation, 4_600).unwrap(), 7);
+        // Likewise for order queues across markets.
+        assert_eq!(registry.record_run(CrankKind::BatchProcess, 1_000).unwrap(), 3);
+        assert_eq!(registry.record_run(CrankKind::BatchProcess, 1_005).unwrap(), 0);
+        assert_eq!(registry.record_run(CrankKind::BatchProcess, 1_010).unwrap(), 3);
+        // Protocol-wide cranks still refuse to run early.
+        assert_eq!(registry.record_run(CrankKind::EpochRollover, 1_000).unwrap(), 6);
+        assert_eq!(
+            registry.record_run(CrankKind::EpochRollover, 1_000 + 86_399).unwrap_err(),
+            error!(CustomError::CrankTooEarly)
+        );
+        assert_eq!(registry.record_run(CrankKind::EpochRollover, 1_000 + 86_400).unwrap(), 6);
+    }
+
+    #[test]
+    fn borrowing_and_liquidation_share_the_collateral_bound() {
+        // Flooring `borrow * 150 / 100` used to admit loans that were liquidatable at once.
+        assert!(!collateral_covers(1, 1));
+        assert!(collateral_covers(2, 1));
+        assert!(!collateral_covers(149, 100));
+        assert!(collateral_covers(150, 100));
+        assert!(!collateral_covers(u64::MAX, u64::MAX));
+        assert!(collateral_covers(u64::MAX, u64::MAX / 3 * 2));
+    }
+
+    #[test]
+    fn auction_bonds_cover_the_order_notional() {
+        assert_eq!(auction_bond_required(1, 1), AUCTION_MIN_BOND);
+        assert_eq!(auction_bond_required(1_000, 10_000), 100_000);
+        assert_eq!(auction_bond_required(1_001, 1_001), 10_020);
+        assert_eq!(auction_bond_required(u64::MAX, u64::MAX), u64::MAX);
+    }
+
+    fn tier(min_stake_tokens: u64, fee_discount_bps: u16) -> TierParams {
+        TierParams {
+            min_stake_tokens,
+            fee_discount_bps,
+            rebate_bps: 0,
+            execution_priority: false,
+            boost_multiplier_bps: 10_000,
+        }
+    }
+
+    #[test]
+    fn tier_tables_are_validated_and_scaled_to_the_mint() {
+        let mut config = TierConfig {
+            state: Pubkey::default(),
+            decimals: 6,
+            tiers: Vec::new(),
+            bump: 0,
+        };
+        config.set_tiers(&[tier(1_000, 500), tier(10_000, 1_000)]).unwrap();
+        assert_eq!(config.tiers[1].min_stake, 10_000_000_000);
+        assert!(config.resolve(999_999_999).is_none());
+        assert_eq!(config.resolve(1_000_000_000).unwrap().fee_discount_bps, 500);
+        assert_eq!(config.resolve(u64::MAX).unwrap().fee_discount_bps, 1_000);
+
+        // Empty, oversized, unordered, duplicated or out-of-range tables are rejected and leave
+        // the current table untouched.
+        assert!(config.set_tiers(&[]).is_err());
+        assert!(config.set_tiers(&[tier(1, 0); TierConfig::MAX_TIERS + 1]).is_err());
+        assert!(config.set_tiers(&[tier(10, 0), tier(1, 0)]).is_err());
+        assert!(config.set_tiers(&[tier(10, 0), tier(10, 0)]).is_err());
+        assert!(config.set_tiers(&[tier(10, 10_001)]).is_err());
+        assert!(config.set_tiers(&[TierParams { boost_multiplier_bps: 0, ..tier(10, 0) }]).is_err());
+        // The minimum stake must not overflow once scaled by the mint's decimals.
+        assert!(config.set_tiers(&[tier(u64::MAX / 1_000_000 + 1, 0)]).is_err());
+        assert_eq!(config.tiers.len(), 2);
+    }
+
+    #[test]
+    fn staking_pool_shares_inflows_by_weight() {
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut a = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let mut b = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let mut pool = StakingPool {
+            state: Pubkey::default(),
+            total_weight: 0,
+            acc_reward_per_share: 0,
+            reserved: 0,
+            bump: 0,
+        };
+        // Inflows while nobody is staked wait for the first staker.
+        pool.sync(500);
+        assert_eq!((pool.acc_reward_per_share, pool.reserved), (0, 0));
+
+        pool.reweight(&mut a, 100);
+        pool.reweight(&mut b, 300);
+        pool.sync(500);
+        pool.sync(500); // Already accounted for.
+        pool.reweight(&mut a, 100);
+        pool.reweight(&mut b, 0);
+        assert_eq!((a.pending_staking_yield, b.pending_staking_yield), (125, 375));
+        assert_eq!(pool.total_weight, 100);
+
+        // Joining after an inflow earns none of it.
+        pool.reweight(&mut b, 100);
+        pool.sync(700);
+        pool.reweight(&mut a, 100);
+        pool.reweight(&mut b, 100);
+        assert_eq!((a.pending_staking_yield, b.pending_staking_yield), (225, 475));
+
+        // Shares round down against each user's debt, so payouts never exceed the inflows.
+        pool.reweight(&mut a, 1);
+        pool.reweight(&mut b, 2);
+        pool.sync(701);
+        pool.reweight(&mut a, 1);
+        pool.reweight(&mut b, 2);
+        assert_eq!((a.pending_staking_yield, b.pending_staking_yield), (225, 476));
+        assert!(a.pending_staking_yield + b.pending_staking_yield <= pool.reserved);
+    }
+
+    #[test]
+    fn fee_quote_applies_discounts_promotions_and_rebates() {
+        let zeroed = vec![0u8; 8 + State::LEN];
+        let mut state = State::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        state.fee_rate = 1_000;
+        let zeroed = vec![0u8; 8 + Market::LEN];
+        let mut market = Market::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let zeroed = vec![0u8; 8 + UserState::LEN];
+        let mut user_state = UserState::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        user_state.fee_discount = 2_500;
+        user_state.trading_rebate = 1_000;
+        market.promo_discount_bps = 1_000;
+        market.promo_start = 100;
+        market.promo_end = 200;
+        let quote = |state: &State, market: &Market, user_state: &UserState, profile, side, now| {
+            let q = compute_fee_quote(state, market, user_state, profile, 1_000_000, side, now);
+            (q.fee_rate, q.base_fee, q.discount, q.rebate, q.effective_fee)
+        };
+
+        // 1000 at the state rate, -25% tier discount, -10% promotion, 10% maker rebate (rounded down).
+        assert_eq!(quote(&state, &market, &user_state, None, LiquiditySide::Maker, 150), (1_000, 1_000, 325, 67, 608));
+        assert_eq!(quote(&state, &market, &user_state, None, LiquiditySide::Taker, 150), (1_000, 1_000, 325, 0, 675));
+        // The promotion window is half-open.
+        assert_eq!(quote(&state, &market, &user_state, None, LiquiditySide::Taker, 200), (1_000, 1_000, 250, 0, 750));
+        // A market rate overrides the state's.
+        market.fee_rate = 2_000;
+        assert_eq!(quote(&state, &market, &user_state, None, LiquiditySide::Taker, 0), (2_000, 2_000, 500, 0, 1_500));
+
+        // Institutions pay their schedule without the tier discount; promotions still apply.
+        user_state.is_institutional = true;
+        market.institutional_fee_rate = 500;
+        assert_eq!(quote(&state, &market, &user_state, None, LiquiditySide::Taker, 150), (500, 500, 50, 0, 450));
+        let zeroed = vec![0u8; 8 + InstitutionalProfile::LEN];
+        let mut profile = InstitutionalProfile::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        profile.fee_rate = 200;
+        profile.expires_at = 1_000;
+        assert_eq!(quote(&state, &market, &user_state, Some(&profile), LiquiditySide::Taker, 0), (200, 200, 0, 0, 200));
+        // An expired profile falls back to the market's institutional schedule.
+        assert_eq!(quote(&state, &market, &user_state, Some(&profile), LiquiditySide::Taker, 1_000), (500, 500, 0, 0, 500));
+        // A profile only counts while the user is flagged institutional.
+        user_state.is_institutional = false;
+        assert_eq!(quote(&state, &market, &user_state, Some(&profile), LiquiditySide::Taker, 0), (2_000, 2_000, 500, 0, 1_500));
+
+        // Fees on tiny notionals round down to zero.
+        let tiny = compute_fee_quote(&state, &market, &user_state, None, 499, LiquiditySide::Maker, 0);
+        assert_eq!((tiny.base_fee, tiny.effective_fee), (0, 0));
+    }
+
+    fn auction_order(id: u64, side: OrderSide, price: u64, size: u64, priority: bool) -> AuctionOrder {
+        AuctionOrder {
+            order: QueuedOrder {
+                id,
+                owner: Pubkey::default(),
+                side,
+                price,
+                size,
+                enqueued_at: 0,
+                priority,
+                stake_score: 0,
+            },
+            filled: 0,
+        }
+    }
+
+    #[test]
+    fn batch_auction_clears_at_the_volume_maximising_price() {
+        use OrderSide::{Ask, Bid};
+        let mut orders = vec![
+            auction_order(0, Bid, 100, 10, false),
+            auction_order(1, Bid, 105, 10, false),
+            auction_order(2, Bid, 100, 10, true),
+            auction_order(3, Ask, 95, 5, false),
+            auction_order(4, Ask, 100, 20, false),
+            auction_order(5, Ask, 110, 10, false),
+        ];
+        // At 100: demand 30, supply 25. At 105: demand 10. At 95: supply 5.
+        assert_eq!(uniform_clearing_price(&orders), (100, 25));
+        allocate_auction_fills(&mut orders, Bid, 100, 25);
+        allocate_auction_fills(&mut orders, Ask, 100, 25);
+        // Better prices fill first; at the marginal price priority wins over commit order.
+        let filled: Vec<u64> = orders.iter().map(|o| o.filled).collect();
+        assert_eq!(filled, vec![5, 10, 10, 5, 20, 0]);
+
+        // Equal volume and imbalance: the lowest price wins.
+        let orders = vec![auction_order(0, Bid, 110, 10, false), auction_order(1, Ask, 100, 10, false)];
+        assert_eq!(uniform_clearing_price(&orders), (100, 10));
+        // A book that does not cross matches nothing.
+        let orders = vec![auction_order(0, Bid, 90, 10, false), auction_order(1, Ask, 100, 10, false)];
+        assert_eq!(uniform_clearing_price(&orders).1, 0);
+        assert_eq!(uniform_clearing_price(&[]), (0, 0));
+    }
+
+    #[test]
+    fn proposal_actions_are_validated_before_voting() {
+        use ProposalAction::*;
+        let rejects = |action: ProposalAction, err: CustomError| {
+            assert_eq!(action.validate().unwrap_err(), error!(err));
+        };
+
+        assert!(SetFeeRate { fee_rate: 500 }.validate().is_ok());
+        rejects(SetFeeRate { fee_rate: 5001 }, CustomError::InvalidFeeRate);
+
+        assert!(SetTiers { tiers: vec![tier(10, 100)] }.validate().is_ok());
+        rejects(SetTiers { tiers: vec![] }, CustomError::InvalidTierConfig);
+
+        let spend = |bucket, amount| TreasurySpend { bucket, recipient: Pubkey::default(), amount };
+        assert!(spend(3, 1).validate().is_ok());
+        rejects(spend(4, 1), CustomError::InvalidTreasuryBucket);
+        rejects(spend(0, 0), CustomError::InvalidProposal);
+
+        assert!(SetTreasurySplit { split_bps: [6000, 2000, 1000, 1000] }.validate().is_ok());
+        rejects(SetTreasurySplit { split_bps: [6000, 2000, 1000, 999] }, CustomError::InvalidTreasurySplit);
+
+        let claim = |vesting_cliff, vesting_duration, vesting_accel_bps| SetClaimConfig {
+            claim_cooldown: 0,
+            vesting_cliff,
+            vesting_duration,
+            vesting_accel_bps,
+        };
+        assert!(claim(86400, 86400, 10_000).validate().is_ok());
+        rejects(claim(86401, 86400, 0), CustomError::InvalidVestingConfig);
+        rejects(claim(0, 0, 0), CustomError::InvalidVestingConfig);
+        rejects(claim(0, 86400, 10_001), CustomError::InvalidVestingConfig);
+
+        assert!(SetUnstakeConfig { unbonding_period: 0, early_exit_penalty_bps: 10_000 }.validate().is_ok());
+        rejects(
+            SetUnstakeConfig { unbonding_period: -1, early_exit_penalty_bps: 0 },
+            CustomError::InvalidUnstakeConfig,
+        );
+
+        let governance = |voting_period, timelock_delay| SetGovernanceConfig {
+            voting_period,
+            quorum_votes: 1,
+            timelock_delay,
+        };
+        assert!(governance(MAX_VOTING_PERIOD, 0).validate().is_ok());
+        rejects(governance(MAX_VOTING_PERIOD + 1, 0), CustomError::InvalidDelay);
+        rejects(governance(0, 0), CustomError::InvalidDelay);
+        rejects(governance(86400, -1), CustomError::InvalidDelay);
+
+        assert!(SetPaused { paused: true }.validate().is_ok());
+        assert!(SetGuardian { guardian: Pubkey::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn direct_votes_override_the_delegate() {
+        let zeroed = vec![0u8; 8 + Proposal::LEN];
+        let mut proposal = Proposal::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let zeroed = vec![0u8; 8 + VoteRecord::LEN];
+        let record = VoteRecord::try_deserialize_unchecked(&mut zeroed.as_slice()).unwrap();
+        let (mut delegate, mut early, mut late) = (record.clone(), record.clone(), record);
+
+        // A delegator voting before its delegate removes that power from the delegate's weight.
+        proposal.override_delegate(&mut delegate, 30);
+        proposal.count_vote(&mut early, false, 30).unwrap();
+        proposal.count_vote(&mut delegate, true, 100).unwrap();
+        assert_eq!((delegate.weight, proposal.for_votes, proposal.against_votes), (70, 70, 30));
+
+        // Voting after the delegate un-counts it from the delegate's side.
+        proposal.override_delegate(&mut delegate, 50);
+        proposal.count_vote(&mut late, false, 50).unwrap();
+        assert_eq!((delegate.weight, proposal.for_votes, proposal.against_votes), (20, 20, 80));
+        assert_eq!(delegate.overridden_power, 80);
+
+        // Overrides never take back more than the delegate still counts.
+        proposal.override_delegate(&mut delegate, 25);
+        assert_eq!((delegate.weight, proposal.for_votes), (0, 0));
+
+        // A voter whose power was entirely taken back has nothing left to cast.
+        let mut drained = VoteRecord { overridden_power: 10, voted: false, ..delegate };
+        assert_eq!(
+            proposal.count_vote(&mut drained, true, 10).unwrap_err(),
+            error!(CustomError::NoVotingPower)
+        );
+    }
+
+    #[test]
+    fn order_queue_counts_each_owners_orders() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut queue = OrderQueue::zeroed();
+        for (i, slot) in queue.orders.iter_mut().take(OrderQueue::MAX_ORDERS_PER_OWNER + 1).enumerate() {
+            slot.owner = if i == 0 { bob } else { alice };
+            slot.in_use = 1;
+        }
+        assert_eq!(queue.queued_by(&alice), OrderQueue::MAX_ORDERS_PER_OWNER);
+        assert_eq!(queue.queued_by(&bob), 1);
+        // Freed slots keep their old owner but no longer count.
+        queue.orders[1].in_use = 0;
+        assert_eq!(queue.queued_by(&alice), OrderQueue::MAX_ORDERS_PER_OWNER - 1);
+    }
 }
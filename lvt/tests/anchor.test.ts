@@ -1,40 +1,204 @@
-// No imports needed: web3, anchor, pg and more are globally available
+// web3, anchor, pg and more are globally available; spl-token creates the LVT mint and treasury.
+import * as spl from "@solana/spl-token";
+
+const TOKEN_PROGRAM_ID = new web3.PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const BUCKET_COUNT = 4;
+
+// Await `promise` and assert that it fails with the named program error.
+async function expectError(promise: Promise<unknown>, code: string) {
+  let failed = false;
+  try {
+    await promise;
+  } catch (err) {
+    failed = true;
+    assert.include(String(err), code);
+  }
+  assert(failed, `expected ${code}`);
+}
+
+function treasuryAuthorityFor(state: web3.PublicKey) {
+  return web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury"), state.toBuffer()],
+    pg.program.programId
+  )[0];
+}
+
+function bucketPdas(state: web3.PublicKey, bucket: number) {
+  const seed = Buffer.from([bucket]);
+  const [treasuryBucket] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("bucket"), state.toBuffer(), seed],
+    pg.program.programId
+  );
+  const [bucketVault] = web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("bucket_vault"), state.toBuffer(), seed],
+    pg.program.programId
+  );
+  return { treasuryBucket, bucketVault };
+}
+
+async function tokenBalance(account: web3.PublicKey) {
+  return Number((await spl.getAccount(pg.connection, account)).amount);
+}
 
 describe("Liquidity Velocity Token", () => {
+  const payer = pg.wallet.keypair;
+  // State is a singleton PDA, so every instruction runs against the same protocol.
+  const [state] = web3.PublicKey.findProgramAddressSync([Buffer.from("state")], pg.program.programId);
+  const treasuryAuthority = treasuryAuthorityFor(state);
+  let lvtMint: web3.PublicKey;
+  let treasury: web3.PublicKey;
+
+  function initBucket(bucket: number) {
+    const { treasuryBucket, bucketVault } = bucketPdas(state, bucket);
+    return pg.program.methods
+      .initTreasuryBucket(bucket)
+      .accounts({
+        state,
+        treasuryBucket,
+        bucketVault,
+        treasuryAuthority,
+        lvtMint,
+        admin: pg.wallet.publicKey,
+        systemProgram: web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+  }
+
+  function distributeAccounts() {
+    const [tradersLps, staking, development, ecosystem] = [0, 1, 2, 3].map((i) =>
+      bucketPdas(state, i)
+    );
+    return {
+      state,
+      treasury,
+      treasuryAuthority,
+      tradersLpsBucket: tradersLps.treasuryBucket,
+      tradersLpsVault: tradersLps.bucketVault,
+      stakingBucket: staking.treasuryBucket,
+      stakingVault: staking.bucketVault,
+      developmentBucket: development.treasuryBucket,
+      developmentVault: development.bucketVault,
+      ecosystemBucket: ecosystem.treasuryBucket,
+      ecosystemVault: ecosystem.bucketVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+  }
+
+  before(async () => {
+    lvtMint = await spl.createMint(pg.connection, payer, pg.wallet.publicKey, null, 6);
+    // The treasury must be an LVT account owned by the program's treasury PDA.
+    treasury = await spl.createAccount(
+      pg.connection,
+      payer,
+      lvtMint,
+      treasuryAuthority,
+      new web3.Keypair()
+    );
+  });
+
+  it("rejects a treasury not owned by the treasury authority", async () => {
+    const walletOwnedTreasury = await spl.createAccount(
+      pg.connection,
+      payer,
+      lvtMint,
+      pg.wallet.publicKey,
+      new web3.Keypair()
+    );
+    await expectError(
+      pg.program.methods
+        .initialize()
+        .accounts({
+          state,
+          treasury: walletOwnedTreasury,
+          treasuryAuthority,
+          admin: pg.wallet.publicKey,
+          lvtMint,
+          systemProgram: web3.SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc(),
+      "InvalidTreasuryAuthority"
+    );
+  });
+
   it("initialize", async () => {
-    // Generate keypairs for state, treasury, and the LVT mint.
-    const stateKp = new web3.Keypair();
-    const treasuryKp = new web3.Keypair();
-    const mintKp = new web3.Keypair();
-
-    // Send the initialize transaction.
-    // Note: In a real environment, the treasury and mint accounts would be valid SPL token accounts.
-    // For testing in Playground,  assume these keypairs suffice.
-    const txHash = await pg.program.methods.initialize()
+    const txHash = await pg.program.methods
+      .initialize()
       .accounts({
-        state: stateKp.publicKey,
-        treasury: treasuryKp.publicKey,
+        state,
+        treasury,
+        treasuryAuthority,
         admin: pg.wallet.publicKey,
-        lvtMint: mintKp.publicKey,
+        lvtMint,
         systemProgram: web3.SystemProgram.programId,
-        // Use the well-known SPL Token program id.
-        tokenProgram: new web3.PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"),
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
-      .signers([stateKp])
       .rpc();
     console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
-
-    // Confirm the transaction.
     await pg.connection.confirmTransaction(txHash);
 
-    // Fetch the created state account.
-    const stateAccount = await pg.program.account.state.fetch(stateKp.publicKey);
-    console.log("On-chain state:", stateAccount);
+    const stateAccount = await pg.program.account.state.fetch(state);
 
     // Check that the initial state values are set as expected.
     assert(stateAccount.totalTrades.eq(new BN(0)));
     assert(stateAccount.totalLiquidity.eq(new BN(0)));
     assert(stateAccount.feeRate.eq(new BN(1000)));
-    assert(stateAccount.treasury.equals(treasuryKp.publicKey));
+    assert(stateAccount.treasury.equals(treasury));
+    assert(stateAccount.admin.equals(pg.wallet.publicKey));
+    assert(stateAccount.tradeVenue.equals(pg.wallet.publicKey));
+    assert(stateAccount.lvtMint.equals(lvtMint));
+    assert.deepEqual(stateAccount.treasurySplitBps, [6000, 2000, 1000, 1000]);
+  });
+
+  it("initializes the treasury buckets", async () => {
+    for (let i = 0; i < BUCKET_COUNT; i++) {
+      await initBucket(i);
+      const { treasuryBucket, bucketVault } = bucketPdas(state, i);
+      const bucket = await pg.program.account.treasuryBucket.fetch(treasuryBucket);
+      assert.equal(bucket.bucket, i);
+      assert(bucket.vault.equals(bucketVault));
+      const vault = await spl.getAccount(pg.connection, bucketVault);
+      assert(vault.owner.equals(treasuryAuthority));
+    }
+  });
+
+  it("rejects an out-of-range bucket", async () => {
+    await expectError(initBucket(BUCKET_COUNT), "InvalidTreasuryBucket");
+  });
+
+  it("rejects distributing an empty treasury", async () => {
+    await expectError(
+      pg.program.methods.distributeTreasury().accounts(distributeAccounts()).rpc(),
+      "NothingToDistribute"
+    );
+  });
+
+  it("rejects a vault that does not belong to its bucket", async () => {
+    await spl.mintTo(pg.connection, payer, lvtMint, treasury, payer, 1_000);
+    const accounts = distributeAccounts();
+    await expectError(
+      pg.program.methods
+        .distributeTreasury()
+        .accounts({ ...accounts, tradersLpsVault: accounts.stakingVault })
+        .rpc(),
+      "ConstraintAddress"
+    );
+  });
+
+  it("distributes the treasury by the configured split", async () => {
+    // 1_000 from the previous case plus 9_001 leaves one unit of rounding dust.
+    await spl.mintTo(pg.connection, payer, lvtMint, treasury, payer, 9_001);
+    await pg.program.methods.distributeTreasury().accounts(distributeAccounts()).rpc();
+
+    const expected = [6000, 2000, 1000, 1000];
+    for (let i = 0; i < BUCKET_COUNT; i++) {
+      const { treasuryBucket, bucketVault } = bucketPdas(state, i);
+      assert.equal(await tokenBalance(bucketVault), expected[i]);
+      const bucket = await pg.program.account.treasuryBucket.fetch(treasuryBucket);
+      assert(bucket.totalDistributed.eq(new BN(expected[i])));
+    }
+    assert.equal(await tokenBalance(treasury), 1);
   });
 });